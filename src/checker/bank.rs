@@ -0,0 +1,155 @@
+use serde_json::Value;
+
+use crate::{
+    checker::{Checker, Verdict, op_spans},
+    runtime::history::{EventKind, History},
+};
+
+// How many transfer events before a bad read are shown with it.
+const PRECEDING_EVENTS: usize = 10;
+
+// Checks a bank workload: money only moves between accounts, so every read of all
+// balances, {"value": {"<account>": balance, ...}}, has to add up to the same total,
+// and no account may ever be overdrawn.
+pub struct BankInvariant {
+    pub total: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BankViolation {
+    // the read that broke the invariant
+    pub read: String,
+    pub problems: Vec<String>,
+    // transfers invoked or completed right before the read completed, oldest first
+    pub preceding: Vec<String>,
+    // how many other reads broke it afterwards
+    pub later_violations: usize,
+}
+
+impl BankInvariant {
+    pub fn new(total: i64) -> Self {
+        BankInvariant { total }
+    }
+
+    fn problems(&self, balances: &Value) -> Vec<String> {
+        let Some(balances) = balances.as_object() else {
+            return vec![format!("balances are not a map of accounts: {}", balances)];
+        };
+        let mut problems = vec![];
+        let mut total = 0;
+        for (account, balance) in balances {
+            let Some(balance) = balance.as_i64() else {
+                problems.push(format!(
+                    "account {} has no numeric balance: {}",
+                    account, balance
+                ));
+                continue;
+            };
+            if balance < 0 {
+                problems.push(format!("account {} is overdrawn at {}", account, balance));
+            }
+            total += balance;
+        }
+        if total != self.total {
+            problems.push(format!("total is {}, expected {}", total, self.total));
+        }
+        problems
+    }
+
+    pub fn first_violation(&self, history: &History) -> Option<BankViolation> {
+        let spans = op_spans(history);
+        let mut reads: Vec<_> = spans
+            .iter()
+            .filter(|s| s.f() == "read" && s.is_ok())
+            .filter_map(|s| Some((s.completed_at()?, s)))
+            .collect();
+        reads.sort_by_key(|(at, _)| *at);
+
+        let mut bad_reads = reads.iter().filter_map(|(at, read)| {
+            let balances = read.value().get("value").unwrap_or(&Value::Null);
+            let problems = self.problems(balances);
+            (!problems.is_empty()).then_some((*at, read, problems))
+        });
+        let (at, read, problems) = bad_reads.next()?;
+        let (completion, _) = read.completion.unwrap();
+
+        let transfers: Vec<String> = history
+            .0
+            .iter()
+            .filter(|e| e.at <= at)
+            .filter(|e| matches!(&e.kind, EventKind::Op(op) if op.f == "transfer"))
+            .map(|e| e.to_string())
+            .collect();
+        let preceding = transfers[transfers.len().saturating_sub(PRECEDING_EVENTS)..].to_vec();
+
+        Some(BankViolation {
+            read: format!(
+                "{}#{} {}",
+                completion.client, completion.msg_id, completion.value
+            ),
+            problems,
+            preceding,
+            later_violations: bad_reads.count(),
+        })
+    }
+}
+
+impl Checker for BankInvariant {
+    fn name(&self) -> String {
+        format!("bank total of {}", self.total)
+    }
+
+    fn check(&self, history: &History) -> Verdict {
+        let Some(violation) = self.first_violation(history) else {
+            return Verdict::valid();
+        };
+        let mut problem = format!("{}: {}", violation.read, violation.problems.join(", "));
+        if violation.later_violations > 0 {
+            problem.push_str(&format!(
+                " ({} more reads broke the invariant)",
+                violation.later_violations
+            ));
+        }
+        problem.push_str(", after:");
+        for event in &violation.preceding {
+            problem.push_str(&format!("\n  {}", event));
+        }
+        Verdict::from_problems(vec![problem])
+    }
+}
+
+#[test]
+fn test_bank_reports_first_read_breaking_the_invariant() {
+    use crate::checker::op_event;
+    use crate::runtime::history::OpType::{Info, Invoke, Ok};
+    use serde_json::json;
+
+    let balances = |a: i64, b: i64| json!({"value": {"0": a, "1": b}});
+    let transfer = json!({"from": 0, "to": 1, "amount": 30});
+    let history = History(vec![
+        op_event(0, "c1", "n1", 1, Invoke, "read", json!({})),
+        op_event(5, "c1", "n1", 1, Ok, "read", balances(50, 50)),
+        op_event(10, "c2", "n2", 1, Invoke, "transfer", transfer.clone()),
+        op_event(20, "c2", "n2", 1, Info, "transfer", transfer),
+        op_event(30, "c1", "n1", 2, Invoke, "read", json!({})),
+        op_event(35, "c1", "n1", 2, Ok, "read", balances(-10, 80)),
+        op_event(40, "c1", "n1", 3, Invoke, "read", json!({})),
+        op_event(45, "c1", "n1", 3, Ok, "read", balances(20, 80)),
+    ]);
+
+    let violation = BankInvariant::new(100).first_violation(&history).unwrap();
+    assert!(violation.read.starts_with("c1#2"));
+    assert_eq!(
+        violation.problems,
+        vec![
+            "account 0 is overdrawn at -10".to_string(),
+            "total is 70, expected 100".to_string()
+        ]
+    );
+    assert_eq!(violation.preceding.len(), 2);
+    assert_eq!(violation.later_violations, 0);
+
+    assert!(!BankInvariant::new(100).check(&history).valid);
+    let fine = History(history.0[..2].to_vec());
+    assert!(BankInvariant::new(100).check(&fine).valid);
+}
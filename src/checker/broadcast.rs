@@ -0,0 +1,213 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use crate::{
+    checker::{Checker, OpSpan, Percentiles, Verdict, op_spans},
+    packet::NodeId,
+    runtime::{history::History, routing::destinations},
+};
+
+// Checks a broadcast workload: every acknowledged message has to be in the last read of
+// every node, and no node may read a message that was never broadcast.
+pub struct BroadcastCompleteness {
+    pub nodes: Vec<NodeId>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BroadcastReport {
+    // acknowledged messages missing from a node's last read
+    pub missing: BTreeMap<NodeId, BTreeSet<u64>>,
+    // messages a node read that nobody tried to broadcast
+    pub unexpected: BTreeMap<NodeId, BTreeSet<u64>>,
+    // nodes that never completed a read
+    pub unread: Vec<NodeId>,
+    // reads that left out a message acknowledged before the read started
+    pub stale_reads: BTreeMap<NodeId, usize>,
+    // packets between nodes per client operation
+    pub msgs_per_op: f64,
+    // from a broadcast's invocation until every node has read the message
+    pub latency: Percentiles,
+}
+
+// Messages are the unique integers the broadcast workload generates.
+fn messages(read: &OpSpan) -> BTreeSet<u64> {
+    match read.value().get("messages") {
+        Some(Value::Array(messages)) => messages.iter().filter_map(Value::as_u64).collect(),
+        _ => BTreeSet::new(),
+    }
+}
+
+impl BroadcastCompleteness {
+    pub fn new(nodes: Vec<NodeId>) -> Self {
+        BroadcastCompleteness { nodes }
+    }
+
+    pub fn analyze(&self, history: &History) -> BroadcastReport {
+        let spans = op_spans(history);
+        let mut report = BroadcastReport::default();
+
+        let attempted: BTreeSet<u64> = spans
+            .iter()
+            .filter(|s| s.f() == "broadcast")
+            .filter_map(|s| s.invoke.value.get("message").and_then(Value::as_u64))
+            .collect();
+        // message -> (invoked at, acknowledged at)
+        let acked: BTreeMap<u64, (Duration, Duration)> = spans
+            .iter()
+            .filter(|s| s.f() == "broadcast" && s.is_ok())
+            .filter_map(|s| {
+                let message = s.invoke.value.get("message")?.as_u64()?;
+                Some((message, (s.invoked_at, s.completed_at()?)))
+            })
+            .collect();
+
+        let mut reads: HashMap<&str, Vec<(&OpSpan, BTreeSet<u64>)>> = HashMap::new();
+        for read in spans.iter().filter(|s| s.f() == "read" && s.is_ok()) {
+            reads
+                .entry(read.invoke.node.as_str())
+                .or_default()
+                .push((read, messages(read)));
+        }
+
+        // message -> when each node first read it
+        let mut seen_at: BTreeMap<u64, Vec<Duration>> = BTreeMap::new();
+        for node in &self.nodes {
+            let Some(node_reads) = reads.get_mut(node.as_str()) else {
+                report.unread.push(node.clone());
+                continue;
+            };
+            node_reads.sort_by_key(|(read, _)| read.completed_at());
+
+            let mut first_seen = BTreeMap::new();
+            for (read, read_messages) in node_reads.iter() {
+                let stale = acked.iter().any(|(m, (_, acked_at))| {
+                    *acked_at < read.invoked_at && !read_messages.contains(m)
+                });
+                if stale {
+                    *report.stale_reads.entry(node.clone()).or_default() += 1;
+                }
+                for m in read_messages {
+                    first_seen
+                        .entry(*m)
+                        .or_insert(read.completed_at().unwrap_or_default());
+                }
+                let unexpected: BTreeSet<u64> =
+                    read_messages.difference(&attempted).copied().collect();
+                if !unexpected.is_empty() {
+                    report
+                        .unexpected
+                        .entry(node.clone())
+                        .or_default()
+                        .extend(unexpected);
+                }
+            }
+            for (m, at) in first_seen {
+                seen_at.entry(m).or_default().push(at);
+            }
+
+            let (_, last_read) = node_reads.last().unwrap();
+            let missing: BTreeSet<u64> = acked
+                .keys()
+                .filter(|m| !last_read.contains(m))
+                .copied()
+                .collect();
+            if !missing.is_empty() {
+                report.missing.insert(node.clone(), missing);
+            }
+        }
+
+        report.latency = Percentiles::of(
+            acked
+                .iter()
+                .filter_map(|(m, (invoked_at, _))| {
+                    let seen = seen_at.get(m)?;
+                    (seen.len() == self.nodes.len())
+                        .then(|| seen.iter().max().unwrap().saturating_sub(*invoked_at))
+                })
+                .collect(),
+        );
+
+        let between_nodes: usize = history
+            .packets()
+            .filter(|(event, _)| self.nodes.contains(&event.node))
+            .map(|(_, packet)| destinations(packet, self.nodes.iter()).len())
+            .sum();
+        let completed = spans.iter().filter(|s| s.completion.is_some()).count();
+        if completed > 0 {
+            report.msgs_per_op = between_nodes as f64 / completed as f64;
+        }
+
+        report
+    }
+}
+
+impl Checker for BroadcastCompleteness {
+    fn name(&self) -> String {
+        "broadcast completeness".to_string()
+    }
+
+    fn check(&self, history: &History) -> Verdict {
+        let report = self.analyze(history);
+        let mut problems = vec![];
+        for node in &report.unread {
+            problems.push(format!("{} never completed a read", node));
+        }
+        for (node, missing) in &report.missing {
+            problems.push(format!(
+                "{} is missing {} acknowledged messages: {:?}",
+                node,
+                missing.len(),
+                missing
+            ));
+        }
+        for (node, unexpected) in &report.unexpected {
+            problems.push(format!(
+                "{} read messages nobody broadcast: {:?}",
+                node, unexpected
+            ));
+        }
+        Verdict::from_problems(problems)
+    }
+}
+
+#[test]
+fn test_broadcast_reports_missing_unexpected_and_latency() {
+    use crate::checker::op_event;
+    use crate::runtime::history::OpType::{Invoke, Ok};
+    use serde_json::json;
+
+    let all_read = json!({"messages": [1, 2, 7]});
+    let history = History(vec![
+        op_event(0, "c1", "n1", 1, Invoke, "broadcast", json!({"message": 1})),
+        op_event(10, "c1", "n1", 1, Ok, "broadcast", json!({"message": 1})),
+        op_event(0, "c2", "n2", 1, Invoke, "broadcast", json!({"message": 2})),
+        op_event(20, "c2", "n2", 1, Ok, "broadcast", json!({"message": 2})),
+        op_event(30, "c1", "n1", 2, Invoke, "read", json!({})),
+        op_event(40, "c1", "n1", 2, Ok, "read", json!({"messages": [1, 2]})),
+        op_event(30, "c2", "n2", 2, Invoke, "read", json!({})),
+        op_event(35, "c2", "n2", 2, Ok, "read", json!({"messages": [2, 7]})),
+        op_event(50, "c2", "n2", 3, Invoke, "read", json!({})),
+        op_event(60, "c2", "n2", 3, Ok, "read", all_read),
+    ]);
+    let checker = BroadcastCompleteness::new(vec!["n1".to_string(), "n2".to_string()]);
+    let report = checker.analyze(&history);
+
+    assert!(report.missing.is_empty());
+    assert_eq!(report.unexpected["n2"], BTreeSet::from([7]));
+    // n2's first read started after message 1 was acknowledged, but didn't have it
+    assert_eq!(report.stale_reads["n2"], 1);
+    // message 1 reached n2 at 60ms, message 2 reached n1 at 40ms
+    assert_eq!(report.latency.max, Duration::from_millis(60));
+    assert_eq!(report.latency.p50, Duration::from_millis(40));
+
+    let verdict = checker.check(&history);
+    assert!(!verdict.valid);
+    assert_eq!(verdict.problems.len(), 1);
+
+    let with_n3 = BroadcastCompleteness::new(vec!["n1".to_string(), "n3".to_string()]);
+    assert_eq!(with_n3.analyze(&history).unread, vec!["n3".to_string()]);
+}
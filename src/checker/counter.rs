@@ -0,0 +1,158 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::Value;
+
+use crate::{
+    checker::{Checker, OpSpan, Verdict, op_spans},
+    packet::NodeId,
+    runtime::history::{History, OpType},
+};
+
+// Checks a grow-only counter workload: once the test is over all nodes have to agree on a
+// total that every acknowledged add is part of, and no node's reads may ever go down.
+pub struct CounterConvergence {
+    pub nodes: Vec<NodeId>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CounterReport {
+    // value of each node's last read
+    pub final_values: BTreeMap<NodeId, i64>,
+    // nodes that never completed a read
+    pub unread: Vec<NodeId>,
+    // what the total may be: every acknowledged add, plus any of the ones that may or
+    // may not have happened
+    pub lower_bound: i64,
+    pub upper_bound: i64,
+    // reads that returned less than a read of the same node that completed before them
+    pub decreasing_reads: Vec<String>,
+}
+
+impl CounterConvergence {
+    pub fn new(nodes: Vec<NodeId>) -> Self {
+        CounterConvergence { nodes }
+    }
+
+    pub fn analyze(&self, history: &History) -> CounterReport {
+        let spans = op_spans(history);
+        let mut report = CounterReport::default();
+
+        for add in spans.iter().filter(|s| s.f() == "add") {
+            let delta = add.invoke.value.get("delta").and_then(Value::as_i64);
+            let delta = delta.unwrap_or_default();
+            match add.op_type() {
+                OpType::Ok => {
+                    report.lower_bound += delta;
+                    report.upper_bound += delta;
+                }
+                OpType::Info | OpType::Invoke => {
+                    report.lower_bound += delta.min(0);
+                    report.upper_bound += delta.max(0);
+                }
+                OpType::Fail => {}
+            }
+        }
+
+        let mut reads: HashMap<&str, Vec<(&OpSpan, i64)>> = HashMap::new();
+        for read in spans.iter().filter(|s| s.f() == "read" && s.is_ok()) {
+            if let Some(value) = read.value().get("value").and_then(Value::as_i64) {
+                reads
+                    .entry(read.invoke.node.as_str())
+                    .or_default()
+                    .push((read, value));
+            }
+        }
+
+        for node in &self.nodes {
+            let Some(node_reads) = reads.get_mut(node.as_str()) else {
+                report.unread.push(node.clone());
+                continue;
+            };
+            node_reads.sort_by_key(|(read, _)| read.completed_at());
+
+            for (i, (read, value)) in node_reads.iter().enumerate() {
+                let earlier = node_reads[..i]
+                    .iter()
+                    .filter(|(before, _)| before.completed_at() < Some(read.invoked_at))
+                    .max_by_key(|(_, value)| *value);
+                if let Some((before, before_value)) = earlier
+                    && value < before_value
+                {
+                    report.decreasing_reads.push(format!(
+                        "{} read {} after {}#{} had read {}",
+                        node, value, before.invoke.client, before.invoke.msg_id, before_value
+                    ));
+                }
+            }
+
+            let (_, last) = node_reads.last().unwrap();
+            report.final_values.insert(node.clone(), *last);
+        }
+
+        report
+    }
+}
+
+impl Checker for CounterConvergence {
+    fn name(&self) -> String {
+        "counter convergence".to_string()
+    }
+
+    fn check(&self, history: &History) -> Verdict {
+        let report = self.analyze(history);
+        let mut problems = vec![];
+        for node in &report.unread {
+            problems.push(format!("{} never completed a read", node));
+        }
+
+        let mut values: Vec<i64> = report.final_values.values().copied().collect();
+        values.dedup();
+        if values.len() > 1 {
+            problems.push(format!("nodes did not converge: {:?}", report.final_values));
+        }
+        for (node, value) in &report.final_values {
+            if *value < report.lower_bound || *value > report.upper_bound {
+                problems.push(format!(
+                    "{} ended at {}, but acknowledged adds put the total in {}..={}",
+                    node, value, report.lower_bound, report.upper_bound
+                ));
+            }
+        }
+
+        problems.extend(report.decreasing_reads);
+        Verdict::from_problems(problems)
+    }
+}
+
+#[test]
+fn test_counter_convergence_bounds_and_monotonic_reads() {
+    use crate::checker::op_event;
+    use crate::runtime::history::OpType::{Fail, Info, Invoke, Ok};
+    use serde_json::json;
+
+    let history = History(vec![
+        op_event(0, "c1", "n1", 1, Invoke, "add", json!({"delta": 2})),
+        op_event(5, "c1", "n1", 1, Ok, "add", json!({"delta": 2})),
+        op_event(0, "c2", "n2", 1, Invoke, "add", json!({"delta": 3})),
+        op_event(9, "c2", "n2", 1, Info, "add", json!({"delta": 3})),
+        op_event(10, "c2", "n2", 2, Invoke, "add", json!({"delta": 4})),
+        op_event(12, "c2", "n2", 2, Fail, "add", json!({"delta": 4})),
+        op_event(20, "c1", "n1", 2, Invoke, "read", json!({})),
+        op_event(22, "c1", "n1", 2, Ok, "read", json!({"value": 5})),
+        op_event(30, "c1", "n1", 3, Invoke, "read", json!({})),
+        op_event(32, "c1", "n1", 3, Ok, "read", json!({"value": 2})),
+        op_event(30, "c2", "n2", 3, Invoke, "read", json!({})),
+        op_event(32, "c2", "n2", 3, Ok, "read", json!({"value": 5})),
+    ]);
+    let checker = CounterConvergence::new(vec!["n1".to_string(), "n2".to_string()]);
+    let report = checker.analyze(&history);
+
+    assert_eq!((report.lower_bound, report.upper_bound), (2, 5));
+    assert_eq!(report.final_values["n1"], 2);
+    assert_eq!(report.decreasing_reads.len(), 1);
+
+    // n1 went backwards and disagrees with n2, but both values are within the bounds
+    let verdict = checker.check(&history);
+    assert_eq!(verdict.problems.len(), 2);
+    assert!(verdict.problems[0].contains("did not converge"));
+}
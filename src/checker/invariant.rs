@@ -0,0 +1,57 @@
+use crate::{
+    checker::{Checker, NoLogContaining},
+    runtime::history::{Event, EventKind},
+};
+
+// Checked while the test runs, against every event as it enters the history. The first
+// invariant to fail aborts the run, so long runs fail fast instead of running to the end.
+pub trait Invariant: Send {
+    fn name(&self) -> String;
+    fn observe(&mut self, event: &Event) -> Result<(), String>;
+}
+
+// An invariant from a closure, e.g.
+//   invariant("no retries", |e| match e.kind { ... })
+pub fn invariant<F>(name: impl Into<String>, f: F) -> Box<dyn Invariant>
+where
+    F: FnMut(&Event) -> Result<(), String> + Send + 'static,
+{
+    Box::new(FnInvariant {
+        name: name.into(),
+        f,
+    })
+}
+
+struct FnInvariant<F> {
+    name: String,
+    f: F,
+}
+
+impl<F> Invariant for FnInvariant<F>
+where
+    F: FnMut(&Event) -> Result<(), String> + Send,
+{
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn observe(&mut self, event: &Event) -> Result<(), String> {
+        (self.f)(event)
+    }
+}
+
+// Stop as soon as a node logs e.g. "PANIC", rather than after the test.
+impl Invariant for NoLogContaining {
+    fn name(&self) -> String {
+        Checker::name(self)
+    }
+
+    fn observe(&mut self, event: &Event) -> Result<(), String> {
+        match &event.kind {
+            EventKind::Log(log) if log.line.contains(&self.pattern) => {
+                Err(format!("{} logged {:?}", event.node, log.line))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde_json::Value;
+
+use crate::{
+    checker::{Checker, OpSpan, Verdict, op_spans},
+    runtime::history::History,
+};
+
+// Checks a kafka style log workload, see WorkloadKind::Log:
+// - every acknowledged send has to show up in polls that got past its offset
+// - an offset of a key holds one message, and a message sits at one offset
+// - polls return the offsets of a key in increasing order, starting where asked
+// - committed offsets of a key never go back
+pub struct LogConsistency;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LogReport {
+    pub lost_writes: Vec<String>,
+    pub inconsistent_offsets: Vec<String>,
+    pub nonmonotonic_polls: Vec<String>,
+    pub regressed_commits: Vec<String>,
+}
+
+impl LogReport {
+    pub fn problems(&self) -> Vec<String> {
+        [
+            &self.lost_writes,
+            &self.inconsistent_offsets,
+            &self.nonmonotonic_polls,
+            &self.regressed_commits,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect()
+    }
+}
+
+fn describe(span: &OpSpan) -> String {
+    format!(
+        "{}#{} {} {}",
+        span.invoke.client,
+        span.invoke.msg_id,
+        span.f(),
+        span.value()
+    )
+}
+
+// {"<key>": offset, ...} of a request or reply
+fn offsets(value: Option<&Value>) -> BTreeMap<String, u64> {
+    let Some(offsets) = value.and_then(Value::as_object) else {
+        return BTreeMap::new();
+    };
+    offsets
+        .iter()
+        .filter_map(|(key, offset)| Some((key.clone(), offset.as_u64()?)))
+        .collect()
+}
+
+impl LogConsistency {
+    pub fn analyze(&self, history: &History) -> LogReport {
+        let spans = op_spans(history);
+        let mut report = LogReport::default();
+        let ok = |f: &'static str| spans.iter().filter(move |s| s.f() == f && s.is_ok());
+
+        // (key, offset) -> message, from acknowledged sends and from polls
+        let mut at_offset: BTreeMap<(String, u64), (String, &OpSpan)> = BTreeMap::new();
+        // (key, message) -> offset
+        let mut offset_of: HashMap<(String, String), u64> = HashMap::new();
+        let mut record = |key: &str, offset: u64, msg: String, span, report: &mut LogReport| {
+            match at_offset.get(&(key.to_string(), offset)) {
+                Some((other, other_span)) if *other != msg => {
+                    report.inconsistent_offsets.push(format!(
+                        "offset {} of {} is {} in {}, but {} in {}",
+                        offset,
+                        key,
+                        other,
+                        describe(other_span),
+                        msg,
+                        describe(span)
+                    ))
+                }
+                Some(_) => {}
+                None => {
+                    at_offset.insert((key.to_string(), offset), (msg.clone(), span));
+                }
+            }
+            match offset_of.insert((key.to_string(), msg.clone()), offset) {
+                Some(other) if other != offset => report.inconsistent_offsets.push(format!(
+                    "{} of {} is at offset {} and {}, seen in {}",
+                    msg,
+                    key,
+                    other,
+                    offset,
+                    describe(span)
+                )),
+                _ => {}
+            }
+        };
+
+        let mut sends = vec![];
+        for send in ok("send") {
+            let value = send.value();
+            let (Some(key), Some(msg), Some(offset)) = (
+                value.get("key").and_then(Value::as_str),
+                value.get("msg"),
+                value.get("offset").and_then(Value::as_u64),
+            ) else {
+                continue;
+            };
+            record(key, offset, msg.to_string(), send, &mut report);
+            sends.push((key.to_string(), offset, msg.to_string(), send));
+        }
+
+        // highest offset any poll got to, per key
+        let mut polled_up_to: BTreeMap<String, u64> = BTreeMap::new();
+        let mut polled = HashSet::new();
+        for poll in ok("poll") {
+            let requested = offsets(poll.invoke.value.get("offsets"));
+            let Some(msgs) = poll.value().get("msgs").and_then(Value::as_object) else {
+                continue;
+            };
+            for (key, msgs) in msgs {
+                let mut previous = None;
+                for entry in msgs.as_array().into_iter().flatten() {
+                    let (Some(offset), Some(msg)) =
+                        (entry.get(0).and_then(Value::as_u64), entry.get(1))
+                    else {
+                        continue;
+                    };
+                    let from = requested.get(key).copied().unwrap_or(0);
+                    if previous.is_some_and(|p| offset <= p) || offset < from {
+                        report.nonmonotonic_polls.push(format!(
+                            "{} returned offset {} of {} after {:?}, asking from {}",
+                            describe(poll),
+                            offset,
+                            key,
+                            previous,
+                            from
+                        ));
+                    }
+                    previous = Some(offset);
+                    record(key, offset, msg.to_string(), poll, &mut report);
+                    polled.insert((key.clone(), msg.to_string()));
+                    let up_to = polled_up_to.entry(key.clone()).or_default();
+                    *up_to = (*up_to).max(offset);
+                }
+            }
+        }
+
+        for (key, offset, msg, send) in sends {
+            if !polled.contains(&(key.clone(), msg))
+                && polled_up_to.get(&key).is_some_and(|up_to| *up_to > offset)
+            {
+                report.lost_writes.push(format!(
+                    "{} was acknowledged at offset {}, but polls skipped it",
+                    describe(send),
+                    offset
+                ));
+            }
+        }
+
+        // Commits overwrite each other, so a listing can't be lower than the latest commit
+        // or listing of the key that completed before the listing started, unless a
+        // commit of the lower offset may have taken effect after that one.
+        let mut committed: Vec<_> = ok("commit_offsets")
+            .map(|s| (s, offsets(s.invoke.value.get("offsets"))))
+            .chain(ok("list_committed_offsets").map(|s| (s, offsets(s.value().get("offsets")))))
+            .collect();
+        committed.sort_by_key(|(s, _)| s.completed_at());
+        for (i, (list, listed)) in committed.iter().enumerate() {
+            if list.f() != "list_committed_offsets" {
+                continue;
+            }
+            for (key, offset) in listed {
+                let latest = committed[..i]
+                    .iter()
+                    .filter(|(s, _)| s.completed_at() < Some(list.invoked_at))
+                    .filter_map(|(s, offsets)| Some((s, *offsets.get(key)?)))
+                    .next_back();
+                let Some((earlier, earlier_offset)) = latest else {
+                    continue;
+                };
+                let recommitted = committed.iter().any(|(s, offsets)| {
+                    s.f() == "commit_offsets"
+                        && offsets.get(key) == Some(offset)
+                        && s.completed_at() > Some(earlier.invoked_at)
+                });
+                if earlier_offset > *offset && !recommitted {
+                    report.regressed_commits.push(format!(
+                        "{} listed {} at {}, but {} had it at {}",
+                        describe(list),
+                        key,
+                        offset,
+                        describe(earlier),
+                        earlier_offset
+                    ));
+                }
+            }
+        }
+
+        report
+    }
+}
+
+impl Checker for LogConsistency {
+    fn name(&self) -> String {
+        "log consistency".to_string()
+    }
+
+    fn check(&self, history: &History) -> Verdict {
+        Verdict::from_problems(self.analyze(history).problems())
+    }
+}
+
+#[test]
+fn test_log_consistency_finds_each_kind_of_problem() {
+    use crate::checker::op_event;
+    use crate::runtime::history::OpType::{Invoke, Ok};
+    use serde_json::json;
+
+    let mut events = vec![];
+    let mut op = |at: u64, client: &str, msg_id: u64, f: &str, request: Value, reply: Value| {
+        let mut completed = request.clone();
+        completed
+            .as_object_mut()
+            .unwrap()
+            .extend(reply.as_object().unwrap().clone());
+        events.push(op_event(at, client, "n1", msg_id, Invoke, f, request));
+        events.push(op_event(at + 1, client, "n1", msg_id, Ok, f, completed));
+    };
+
+    op(
+        0,
+        "c1",
+        1,
+        "send",
+        json!({"key": "0", "msg": 10}),
+        json!({"offset": 0}),
+    );
+    op(
+        2,
+        "c1",
+        2,
+        "send",
+        json!({"key": "0", "msg": 11}),
+        json!({"offset": 1}),
+    );
+    op(
+        4,
+        "c1",
+        3,
+        "send",
+        json!({"key": "0", "msg": 12}),
+        json!({"offset": 2}),
+    );
+    // misses 11, and has a different message at offset 2
+    let polled = json!({"msgs": {"0": [[0, 10], [2, 13]]}});
+    op(6, "c2", 1, "poll", json!({"offsets": {"0": 0}}), polled);
+    // goes back to offset 0 although it asked from 1
+    let polled = json!({"msgs": {"0": [[0, 10]]}});
+    op(8, "c2", 2, "poll", json!({"offsets": {"0": 1}}), polled);
+    op(
+        10,
+        "c2",
+        3,
+        "commit_offsets",
+        json!({"offsets": {"0": 2}}),
+        json!({}),
+    );
+    let listed = json!({"offsets": {"0": 1}});
+    op(
+        12,
+        "c3",
+        1,
+        "list_committed_offsets",
+        json!({"keys": ["0"]}),
+        listed,
+    );
+
+    let report = LogConsistency.analyze(&History(events));
+    assert_eq!(report.lost_writes.len(), 1);
+    assert!(report.lost_writes[0].contains("\"msg\":11"));
+    assert_eq!(report.inconsistent_offsets.len(), 1);
+    assert_eq!(report.nonmonotonic_polls.len(), 1);
+    assert_eq!(report.regressed_commits.len(), 1);
+}
+
+#[test]
+fn test_commits_of_different_clients_may_go_back() {
+    use crate::checker::op_event;
+    use crate::runtime::history::OpType::{Invoke, Ok};
+    use serde_json::json;
+
+    let op = |at: u64, client: &str, f: &str, request: Value, completed: Value| {
+        [
+            op_event(at, client, "n1", 1, Invoke, f, request),
+            op_event(at + 1, client, "n1", 1, Ok, f, completed),
+        ]
+    };
+    let commit = |offset: u64| json!({"offsets": {"0": offset}});
+    let list = json!({"keys": ["0"]});
+    let mut events = vec![];
+    events.extend(op(0, "c1", "commit_offsets", commit(5), commit(5)));
+    events.extend(op(2, "c2", "commit_offsets", commit(3), commit(3)));
+    events.extend(op(
+        4,
+        "c3",
+        "list_committed_offsets",
+        list.clone(),
+        commit(3),
+    ));
+    assert_eq!(
+        LogConsistency.analyze(&History(events.clone())),
+        LogReport::default()
+    );
+
+    // once listed at 3, going back to 2 needs a commit of 2
+    events.extend(op(6, "c4", "list_committed_offsets", list, commit(2)));
+    let report = LogConsistency.analyze(&History(events));
+    assert_eq!(report.regressed_commits.len(), 1);
+    assert!(report.regressed_commits[0].contains("c3#1 list_committed_offsets"));
+}
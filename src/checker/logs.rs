@@ -0,0 +1,71 @@
+use crate::{
+    checker::{Checker, Verdict},
+    runtime::history::History,
+};
+
+// Fails if any node logged a line containing the pattern, e.g. "PANIC".
+pub struct NoLogContaining {
+    pub pattern: String,
+}
+
+impl NoLogContaining {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        NoLogContaining {
+            pattern: pattern.into(),
+        }
+    }
+}
+
+impl Checker for NoLogContaining {
+    fn name(&self) -> String {
+        format!("no log containing {:?}", self.pattern)
+    }
+
+    fn check(&self, history: &History) -> Verdict {
+        Verdict::from_problems(
+            history
+                .logs()
+                .filter(|(_, log)| log.line.contains(&self.pattern))
+                .map(|(event, _)| format!("{}", event))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+fn log_event(node: &str, line: &str) -> crate::runtime::history::Event {
+    use crate::runtime::history::{Event, EventKind, Log, LogStream};
+
+    Event {
+        at: std::time::Duration::ZERO,
+        node: node.to_string(),
+        kind: EventKind::Log(Log {
+            stream: LogStream::Stderr,
+            line: line.to_string(),
+        }),
+    }
+}
+
+#[test]
+fn test_no_log_containing_passes_on_clean_logs() {
+    let history = History(vec![
+        log_event("node1", "started"),
+        log_event("node2", "ok"),
+    ]);
+    assert_eq!(
+        NoLogContaining::new("PANIC").check(&history),
+        Verdict::valid()
+    );
+}
+
+#[test]
+fn test_no_log_containing_reports_offending_lines() {
+    let history = History(vec![
+        log_event("node1", "started"),
+        log_event("node2", "PANIC: index out of bounds"),
+    ]);
+    let verdict = NoLogContaining::new("PANIC").check(&history);
+    assert!(!verdict.valid);
+    assert_eq!(verdict.problems.len(), 1);
+    assert!(verdict.problems[0].contains("node2"));
+}
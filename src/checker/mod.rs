@@ -0,0 +1,158 @@
+use std::{collections::HashMap, time::Duration};
+
+use serde_json::Value;
+
+use crate::runtime::history::{History, Op, OpType};
+
+pub mod bank;
+pub mod broadcast;
+pub mod counter;
+pub mod invariant;
+pub mod kafka;
+pub mod logs;
+pub mod temporal;
+pub mod txn;
+
+pub use bank::*;
+pub use broadcast::*;
+pub use counter::*;
+pub use invariant::*;
+pub use kafka::*;
+pub use logs::*;
+pub use temporal::*;
+pub use txn::*;
+
+// A checker looks at the history of a finished test and decides whether it is valid.
+pub trait Checker {
+    fn name(&self) -> String;
+    fn check(&self, history: &History) -> Verdict;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Verdict {
+    pub valid: bool,
+    // human readable description of everything that went wrong
+    pub problems: Vec<String>,
+}
+
+impl Verdict {
+    pub fn valid() -> Self {
+        Verdict {
+            valid: true,
+            problems: vec![],
+        }
+    }
+
+    pub fn from_problems(problems: Vec<String>) -> Self {
+        Verdict {
+            valid: problems.is_empty(),
+            problems,
+        }
+    }
+}
+
+// A workload operation from its invocation to its completion.
+#[derive(Clone, Debug)]
+pub struct OpSpan<'a> {
+    pub invoke: &'a Op,
+    pub invoked_at: Duration,
+    // None if the operation never completed before the test ended
+    pub completion: Option<(&'a Op, Duration)>,
+}
+
+impl OpSpan<'_> {
+    // An operation that never completed may or may not have happened, just like an Info one.
+    pub fn op_type(&self) -> OpType {
+        self.completion.map_or(OpType::Info, |(op, _)| op.op_type)
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.op_type() == OpType::Ok
+    }
+
+    pub fn f(&self) -> &str {
+        &self.invoke.f
+    }
+
+    // The completion's value when there is one, since it also has what the node replied.
+    pub fn value(&self) -> &Value {
+        self.completion
+            .map_or(&self.invoke.value, |(op, _)| &op.value)
+    }
+
+    pub fn completed_at(&self) -> Option<Duration> {
+        self.completion.map(|(_, at)| at)
+    }
+}
+
+// Pairs up the invocations and completions of the history, in order of invocation.
+pub fn op_spans(history: &History) -> Vec<OpSpan<'_>> {
+    let mut spans = vec![];
+    let mut pending = HashMap::new();
+    for (event, op) in history.ops() {
+        let key = (op.client.as_str(), op.msg_id);
+        if op.op_type == OpType::Invoke {
+            pending.insert(key, spans.len());
+            spans.push(OpSpan {
+                invoke: op,
+                invoked_at: event.at,
+                completion: None,
+            });
+        } else if let Some(i) = pending.remove(&key) {
+            spans[i].completion = Some((op, event.at));
+        }
+    }
+    spans
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Percentiles {
+    // Nearest rank percentiles, all zero without samples.
+    pub fn of(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Percentiles::default();
+        }
+        samples.sort();
+        let rank = |p: f64| samples[((p * samples.len() as f64).ceil() as usize).max(1) - 1];
+        Percentiles {
+            p50: rank(0.50),
+            p95: rank(0.95),
+            p99: rank(0.99),
+            max: rank(1.0),
+        }
+    }
+}
+
+// Test helper for a workload operation event at the given time.
+#[cfg(test)]
+pub(crate) fn op_event(
+    at_ms: u64,
+    client: &str,
+    node: &str,
+    msg_id: u64,
+    op_type: crate::runtime::history::OpType,
+    f: &str,
+    value: Value,
+) -> crate::runtime::history::Event {
+    use crate::runtime::history::{Event, EventKind, Op};
+
+    Event {
+        at: Duration::from_millis(at_ms),
+        node: client.to_string(),
+        kind: EventKind::Op(Op {
+            client: client.to_string(),
+            node: node.to_string(),
+            msg_id,
+            op_type,
+            f: f.to_string(),
+            value,
+        }),
+    }
+}
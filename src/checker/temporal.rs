@@ -0,0 +1,618 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::Arc,
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use crate::{
+    checker::{Checker, Verdict},
+    runtime::history::{Event, History},
+};
+
+// Values an enclosing bind took from an earlier event, e.g. the msg_id of a request.
+pub type Bindings = BTreeMap<String, Value>;
+
+type Predicate = Arc<dyn Fn(&Event, &Bindings) -> bool + Send + Sync>;
+type Binder = Arc<dyn Fn(&Event) -> Option<Bindings> + Send + Sync>;
+
+// LTL with time bounds (MTL), over the events of a history. A formula holds or not at
+// an event of the history; the temporal operators look at that event and the ones after
+// it, within the window if there is one. The history is finite, so `eventually` fails
+// when it runs out of events and `always` holds. Only a window the history ends in leaves
+// it open, what the history shows then is no counterexample either way.
+//
+//   // every request is answered within 2s
+//   always(bind("request", request_id, eventually_within(Duration::from_secs(2), reply)))
+#[derive(Clone)]
+pub enum Formula {
+    Atom(String, Predicate),
+    Not(Box<Formula>),
+    And(Box<Formula>, Box<Formula>),
+    Or(Box<Formula>, Box<Formula>),
+    Implies(Box<Formula>, Box<Formula>),
+    Next(Box<Formula>),
+    Always(Option<Duration>, Box<Formula>),
+    Eventually(Option<Duration>, Box<Formula>),
+    // the first holds at every event until the second does, which it has to
+    Until(Option<Duration>, Box<Formula>, Box<Formula>),
+    // holds when the binder takes nothing from the event, otherwise the formula has to
+    // hold with what it took
+    Bind(String, Binder, Box<Formula>),
+}
+
+pub fn atom<F>(name: impl Into<String>, predicate: F) -> Formula
+where
+    F: Fn(&Event, &Bindings) -> bool + Send + Sync + 'static,
+{
+    Formula::Atom(name.into(), Arc::new(predicate))
+}
+
+pub fn not(f: Formula) -> Formula {
+    Formula::Not(Box::new(f))
+}
+
+pub fn next(f: Formula) -> Formula {
+    Formula::Next(Box::new(f))
+}
+
+pub fn always(f: Formula) -> Formula {
+    Formula::Always(None, Box::new(f))
+}
+
+pub fn always_within(window: Duration, f: Formula) -> Formula {
+    Formula::Always(Some(window), Box::new(f))
+}
+
+pub fn eventually(f: Formula) -> Formula {
+    Formula::Eventually(None, Box::new(f))
+}
+
+pub fn eventually_within(window: Duration, f: Formula) -> Formula {
+    Formula::Eventually(Some(window), Box::new(f))
+}
+
+pub fn until(f: Formula, g: Formula) -> Formula {
+    Formula::Until(None, Box::new(f), Box::new(g))
+}
+
+pub fn until_within(window: Duration, f: Formula, g: Formula) -> Formula {
+    Formula::Until(Some(window), Box::new(f), Box::new(g))
+}
+
+pub fn bind<F>(name: impl Into<String>, binder: F, f: Formula) -> Formula
+where
+    F: Fn(&Event) -> Option<Bindings> + Send + Sync + 'static,
+{
+    Formula::Bind(name.into(), Arc::new(binder), Box::new(f))
+}
+
+impl Formula {
+    pub fn and(self, g: Formula) -> Formula {
+        Formula::And(Box::new(self), Box::new(g))
+    }
+
+    pub fn or(self, g: Formula) -> Formula {
+        Formula::Or(Box::new(self), Box::new(g))
+    }
+
+    pub fn implies(self, g: Formula) -> Formula {
+        Formula::Implies(Box::new(self), Box::new(g))
+    }
+
+    // What the formula says about event i. Outcomes of subformulas are kept in the memo,
+    // so nested temporal operators don't evaluate their operands again and again.
+    fn eval(&self, memo: &mut Memo, events: &[Event], i: usize, bindings: &Bindings) -> Outcome {
+        let key = (self as *const Formula as usize, i);
+        if let Some(outcome) = memo.0.get(&key) {
+            return outcome.clone();
+        }
+        let outcome = self.eval_at(memo, events, i, bindings);
+        memo.0.insert(key, outcome.clone());
+        outcome
+    }
+
+    fn eval_at(&self, memo: &mut Memo, events: &[Event], i: usize, bindings: &Bindings) -> Outcome {
+        let step = |note: String| vec![Step { event: i, note }];
+        match self {
+            Formula::Atom(name, predicate) => match events.get(i) {
+                Some(event) if predicate(event, bindings) => Outcome::Holds,
+                _ => Outcome::Fails(step(format!("not {}", name))),
+            },
+            Formula::Not(f) => match f.eval(memo, events, i, bindings) {
+                Outcome::Holds => Outcome::Fails(step(format!("{} held", f))),
+                Outcome::Fails(_) => Outcome::Holds,
+                Outcome::Unknown => Outcome::Unknown,
+            },
+            Formula::And(f, g) => match f.eval(memo, events, i, bindings) {
+                Outcome::Fails(trace) => Outcome::Fails(trace),
+                first => match g.eval(memo, events, i, bindings) {
+                    Outcome::Holds => first,
+                    second => second,
+                },
+            },
+            Formula::Or(f, g) => match f.eval(memo, events, i, bindings) {
+                Outcome::Holds => Outcome::Holds,
+                first => match (first, g.eval(memo, events, i, bindings)) {
+                    (Outcome::Fails(mut trace), Outcome::Fails(other)) => {
+                        trace.extend(other);
+                        Outcome::Fails(trace)
+                    }
+                    (_, Outcome::Holds) => Outcome::Holds,
+                    _ => Outcome::Unknown,
+                },
+            },
+            Formula::Implies(f, g) => match f.eval(memo, events, i, bindings) {
+                Outcome::Fails(_) => Outcome::Holds,
+                Outcome::Holds => match g.eval(memo, events, i, bindings) {
+                    Outcome::Fails(trace) => {
+                        let mut steps = step(format!("{} held", f));
+                        steps.extend(trace);
+                        Outcome::Fails(steps)
+                    }
+                    outcome => outcome,
+                },
+                Outcome::Unknown => match g.eval(memo, events, i, bindings) {
+                    Outcome::Holds => Outcome::Holds,
+                    _ => Outcome::Unknown,
+                },
+            },
+            Formula::Next(f) if i + 1 < events.len() => f.eval(memo, events, i + 1, bindings),
+            Formula::Next(_) => Outcome::Fails(step("no event after this one".to_string())),
+            Formula::Always(None, _) | Formula::Eventually(None, _) | Formula::Until(None, ..) => {
+                self.eval_suffix(memo, events, i, bindings)
+            }
+            Formula::Always(Some(window), f) => {
+                let mut outcome = Outcome::Holds;
+                for j in i..window_end(events, i, Some(*window)) {
+                    match f.eval(memo, events, j, bindings) {
+                        Outcome::Holds => {}
+                        Outcome::Fails(trace) => return Outcome::Fails(trace),
+                        Outcome::Unknown => outcome = Outcome::Unknown,
+                    }
+                }
+                outcome
+            }
+            Formula::Eventually(Some(window), f) => {
+                let mut outcome = Outcome::Fails(step(format!(
+                    "{} never followed{}",
+                    f,
+                    within(Some(*window))
+                )));
+                if window_open(events, i, *window) {
+                    outcome = Outcome::Unknown;
+                }
+                for j in i..window_end(events, i, Some(*window)) {
+                    match f.eval(memo, events, j, bindings) {
+                        Outcome::Holds => return Outcome::Holds,
+                        Outcome::Fails(_) => {}
+                        Outcome::Unknown => outcome = Outcome::Unknown,
+                    }
+                }
+                outcome
+            }
+            Formula::Until(Some(window), f, g) => {
+                let mut outcome = Outcome::Fails(step(format!(
+                    "{} never followed{}",
+                    g,
+                    within(Some(*window))
+                )));
+                if window_open(events, i, *window) {
+                    outcome = Outcome::Unknown;
+                }
+                for j in i..window_end(events, i, Some(*window)) {
+                    match g.eval(memo, events, j, bindings) {
+                        Outcome::Holds => return Outcome::Holds,
+                        Outcome::Fails(_) => {}
+                        Outcome::Unknown => outcome = Outcome::Unknown,
+                    }
+                    match f.eval(memo, events, j, bindings) {
+                        Outcome::Holds => {}
+                        Outcome::Fails(trace) => return Outcome::Fails(trace),
+                        Outcome::Unknown => outcome = Outcome::Unknown,
+                    }
+                }
+                outcome
+            }
+            Formula::Bind(name, binder, f) => {
+                let Some(bound) = events.get(i).and_then(|e| binder(e)) else {
+                    return Outcome::Holds;
+                };
+                let mut inner = bindings.clone();
+                inner.extend(bound.clone());
+                // what was evaluated so far holds for other bindings only
+                match f.eval(&mut Memo::default(), events, i, &inner) {
+                    Outcome::Fails(trace) => {
+                        let mut steps = step(format!("{} {}", name, Value::from_iter(bound)));
+                        steps.extend(trace);
+                        Outcome::Fails(steps)
+                    }
+                    outcome => outcome,
+                }
+            }
+        }
+    }
+
+    // Always, eventually and until without a window. Each comes out at an event as it
+    // does at the next one unless the event itself settles it, so the events are walked
+    // up to the first one that settles it or was already evaluated, and all of them are
+    // memoised on the way back.
+    fn eval_suffix(
+        &self,
+        memo: &mut Memo,
+        events: &[Event],
+        i: usize,
+        bindings: &Bindings,
+    ) -> Outcome {
+        let key = |j: usize| (self as *const Formula as usize, j);
+        // outcomes of the operands at the events that don't settle it
+        let mut pending = vec![];
+        let mut j = i;
+        let mut outcome = loop {
+            if j >= events.len() {
+                break match self {
+                    Formula::Eventually(_, f) | Formula::Until(_, _, f) => {
+                        Outcome::Fails(vec![Step {
+                            event: events.len(),
+                            note: format!("{} never followed", f),
+                        }])
+                    }
+                    _ => Outcome::Holds,
+                };
+            }
+            if j > i
+                && let Some(outcome) = memo.0.get(&key(j))
+            {
+                break outcome.clone();
+            }
+            match self {
+                Formula::Always(_, f) => match f.eval(memo, events, j, bindings) {
+                    Outcome::Fails(trace) => break Outcome::Fails(trace),
+                    now => pending.push((now, None)),
+                },
+                Formula::Eventually(_, f) => match f.eval(memo, events, j, bindings) {
+                    Outcome::Holds => break Outcome::Holds,
+                    now => pending.push((now, None)),
+                },
+                Formula::Until(_, f, g) => {
+                    let g_now = g.eval(memo, events, j, bindings);
+                    match (f.eval(memo, events, j, bindings), g_now) {
+                        (_, Outcome::Holds) => break Outcome::Holds,
+                        (Outcome::Fails(trace), Outcome::Fails(_)) => {
+                            break Outcome::Fails(trace);
+                        }
+                        (f_now, g_now) => pending.push((f_now, Some(g_now))),
+                    }
+                }
+                _ => unreachable!(),
+            }
+            j += 1;
+        };
+
+        for (j, (now, g_now)) in (i..j).zip(pending).rev() {
+            outcome = match (self, g_now) {
+                (Formula::Always(..), _) => now.and(outcome),
+                (Formula::Eventually(..), _) => now.or(outcome),
+                (_, Some(g_now)) => g_now.or(now.and(outcome)),
+                _ => unreachable!(),
+            };
+            memo.0.insert(key(j), outcome.clone());
+        }
+        outcome
+    }
+}
+
+// Outcomes of the subformulas at the events they were evaluated at, for one set of
+// bindings.
+#[derive(Default)]
+struct Memo(HashMap<(usize, usize), Outcome>);
+
+// What a formula says about an event. A window that the history ends in can't say yet
+// whether what it waits for comes.
+#[derive(Clone)]
+enum Outcome {
+    Holds,
+    Fails(Vec<Step>),
+    Unknown,
+}
+
+impl Outcome {
+    fn and(self, other: Outcome) -> Outcome {
+        match (self, other) {
+            (Outcome::Fails(trace), _) | (_, Outcome::Fails(trace)) => Outcome::Fails(trace),
+            (Outcome::Holds, Outcome::Holds) => Outcome::Holds,
+            _ => Outcome::Unknown,
+        }
+    }
+
+    fn or(self, other: Outcome) -> Outcome {
+        match (self, other) {
+            (Outcome::Holds, _) | (_, Outcome::Holds) => Outcome::Holds,
+            (Outcome::Fails(_), Outcome::Fails(trace)) => Outcome::Fails(trace),
+            _ => Outcome::Unknown,
+        }
+    }
+}
+
+// Index of the first event after the window that starts at event i.
+fn window_end(events: &[Event], i: usize, window: Option<Duration>) -> usize {
+    let (Some(window), Some(start)) = (window, events.get(i)) else {
+        return events.len().max(i);
+    };
+    i + events[i..]
+        .iter()
+        .take_while(|e| e.at.saturating_sub(start.at) <= window)
+        .count()
+}
+
+// Whether the history ends before the window that starts at event i does.
+fn window_open(events: &[Event], i: usize, window: Duration) -> bool {
+    match (events.get(i), events.last()) {
+        (Some(start), Some(last)) => last.at.saturating_sub(start.at) < window,
+        _ => false,
+    }
+}
+
+fn within(window: Option<Duration>) -> String {
+    match window {
+        Some(window) => format!(" within {:?}", window),
+        None => String::new(),
+    }
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Formula::Atom(name, _) => write!(f, "{}", name),
+            Formula::Not(g) => write!(f, "not {}", g),
+            Formula::And(g, h) => write!(f, "({} and {})", g, h),
+            Formula::Or(g, h) => write!(f, "({} or {})", g, h),
+            Formula::Implies(g, h) => write!(f, "({} implies {})", g, h),
+            Formula::Next(g) => write!(f, "next {}", g),
+            Formula::Always(window, g) => write!(f, "always{} {}", within(*window), g),
+            Formula::Eventually(window, g) => {
+                write!(f, "eventually{} {}", within(*window), g)
+            }
+            Formula::Until(window, g, h) => {
+                write!(f, "({} until{} {})", g, within(*window), h)
+            }
+            Formula::Bind(name, _, g) => write!(f, "for each {}, {}", name, g),
+        }
+    }
+}
+
+// An event of a counterexample, and what about it breaks the property.
+#[derive(Clone)]
+struct Step {
+    event: usize,
+    note: String,
+}
+
+// A formula that has to hold at the start of the history.
+pub struct Property {
+    pub name: String,
+    pub formula: Formula,
+}
+
+impl Property {
+    pub fn new(name: impl Into<String>, formula: Formula) -> Self {
+        Property {
+            name: name.into(),
+            formula,
+        }
+    }
+
+    // The trace of events showing the property doesn't hold, one line per event.
+    pub fn counterexample(&self, history: &History) -> Option<Vec<String>> {
+        let memo = &mut Memo::default();
+        let Outcome::Fails(trace) = self.formula.eval(memo, &history.0, 0, &Bindings::new()) else {
+            return None;
+        };
+        Some(
+            trace
+                .iter()
+                .map(|step| match history.0.get(step.event) {
+                    Some(event) => format!("{}: {}", event, step.note),
+                    None => format!("end of history: {}", step.note),
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Checker for Property {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn check(&self, history: &History) -> Verdict {
+        match self.counterexample(history) {
+            Some(trace) => Verdict::from_problems(vec![format!(
+                "{} does not hold:\n  {}",
+                self.formula,
+                trace.join("\n  ")
+            )]),
+            None => Verdict::valid(),
+        }
+    }
+}
+
+#[cfg(test)]
+fn rpc_event(at_ms: u64, src: &str, dst: &str, body: Value) -> Event {
+    use crate::{
+        packet::{Packet, Payload, Rpc},
+        runtime::history::EventKind,
+    };
+
+    Event {
+        at: Duration::from_millis(at_ms),
+        node: src.to_string(),
+        kind: EventKind::Packet(Packet::Rpc(Rpc {
+            src: src.to_string(),
+            dst: dst.to_string(),
+            data: Payload::Json(body),
+        })),
+    }
+}
+
+#[test]
+fn test_every_request_is_answered_in_time() {
+    use crate::runtime::history::EventKind;
+    use serde_json::json;
+
+    let body = |e: &Event| match &e.kind {
+        EventKind::Packet(packet) => packet.body().map(|body| body.into_owned()),
+        _ => None,
+    };
+    let request = move |e: &Event| {
+        let body = body(e)?;
+        if body.get("in_reply_to").is_some() {
+            return None;
+        }
+        Some(Bindings::from([(
+            "msg_id".to_string(),
+            body.get("msg_id")?.clone(),
+        )]))
+    };
+    let reply = atom("reply", move |e, bindings| {
+        body(e).is_some_and(|body| body.get("in_reply_to") == bindings.get("msg_id"))
+    });
+    let answered = Property::new(
+        "requests are answered",
+        always(bind(
+            "request",
+            request,
+            eventually_within(Duration::from_secs(2), reply),
+        )),
+    );
+
+    let history = History(vec![
+        rpc_event(0, "c1", "n1", json!({"type": "read", "msg_id": 1})),
+        rpc_event(100, "c1", "n1", json!({"type": "read", "msg_id": 2})),
+        rpc_event(
+            500,
+            "n1",
+            "c1",
+            json!({"type": "read_ok", "in_reply_to": 2}),
+        ),
+        rpc_event(
+            2500,
+            "n1",
+            "c1",
+            json!({"type": "read_ok", "in_reply_to": 1}),
+        ),
+    ]);
+    let trace = answered.counterexample(&history).unwrap();
+    assert_eq!(trace.len(), 2);
+    assert!(trace[0].contains("request {\"msg_id\":1}"));
+    assert!(trace[1].ends_with("reply never followed within 2s"));
+
+    let on_time = History(history.0[1..3].to_vec());
+    assert!(answered.check(&on_time).valid);
+}
+
+#[test]
+fn test_at_most_one_leader_per_term() {
+    use crate::runtime::history::{EventKind, Log, LogStream};
+    use serde_json::json;
+
+    let leader = |e: &Event| {
+        let EventKind::Log(log) = &e.kind else {
+            return None;
+        };
+        let term: u64 = log.line.strip_prefix("leader term=")?.parse().ok()?;
+        Some(Bindings::from([
+            ("node".to_string(), json!(e.node)),
+            ("term".to_string(), json!(term)),
+        ]))
+    };
+    let other_leader = atom("another leader in the same term", move |e, bindings| {
+        leader(e).is_some_and(|l| l["term"] == bindings["term"] && l["node"] != bindings["node"])
+    });
+    let unique = Property::new(
+        "one leader per term",
+        always(bind("leader", leader, always(not(other_leader)))),
+    );
+
+    let log = |node: &str, line: &str| Event {
+        at: Duration::ZERO,
+        node: node.to_string(),
+        kind: EventKind::Log(Log {
+            stream: LogStream::Stdout,
+            line: line.to_string(),
+        }),
+    };
+    let fine = History(vec![
+        log("n1", "leader term=1"),
+        log("n2", "leader term=2"),
+        log("n2", "heartbeat"),
+    ]);
+    assert!(unique.check(&fine).valid);
+
+    let split_brain = History(vec![
+        log("n1", "leader term=1"),
+        log("n2", "leader term=1"),
+        log("n2", "heartbeat"),
+    ]);
+    let verdict = unique.check(&split_brain);
+    assert!(!verdict.valid);
+    assert!(verdict.problems[0].contains("another leader in the same term held"));
+}
+
+#[test]
+fn test_window_open_at_the_end_of_the_history_is_inconclusive() {
+    use serde_json::json;
+
+    let reply = atom("reply", |e, _| e.to_string().contains("in_reply_to"));
+    let answered = Property::new(
+        "answered",
+        eventually_within(Duration::from_secs(2), reply.clone()),
+    );
+    let unanswered = Property::new(
+        "unanswered",
+        not(eventually_within(Duration::from_secs(2), reply)),
+    );
+
+    let mut history = History(vec![
+        rpc_event(0, "c1", "n1", json!({"type": "read", "msg_id": 1})),
+        rpc_event(1000, "c1", "n1", json!({"type": "read", "msg_id": 2})),
+    ]);
+    // the reply may still come after the history ends
+    assert!(answered.counterexample(&history).is_none());
+    assert!(unanswered.counterexample(&history).is_none());
+
+    history.0.push(rpc_event(
+        2500,
+        "c1",
+        "n1",
+        json!({"type": "read", "msg_id": 3}),
+    ));
+    assert!(answered.counterexample(&history).is_some());
+    assert!(unanswered.counterexample(&history).is_none());
+}
+
+#[test]
+fn test_nested_operators_evaluate_each_event_once() {
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let evaluated = Arc::new(AtomicUsize::new(0));
+    let counted = evaluated.clone();
+    let even = atom("even msg_id", move |e, _| {
+        counted.fetch_add(1, Ordering::Relaxed);
+        e.to_string().contains("\"msg_id\":0")
+    });
+    let history = History(
+        (0..2000)
+            .map(|i| rpc_event(i, "c1", "n1", json!({"msg_id": i % 2})))
+            .collect(),
+    );
+    let property = Property::new("even again", always(eventually(even)));
+
+    // the history ends on an odd one
+    let trace = property.counterexample(&history).unwrap();
+    assert_eq!(trace, vec!["end of history: even msg_id never followed"]);
+    assert_eq!(evaluated.load(Ordering::Relaxed), history.0.len());
+}
@@ -0,0 +1,655 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt,
+};
+
+use serde_json::Value;
+
+use crate::{
+    checker::{Checker, OpSpan, Verdict, op_spans},
+    runtime::history::{History, OpType},
+};
+
+// Elle style checker for transactional workloads. A txn operation carries a "txn" of
+// micro-ops, either list-append ones:
+//   ["append", key, value] and ["r", key, [values...]]
+// or read-write-register ones:
+//   ["w", key, value] and ["r", key, value]
+// Written values have to be unique per key. From what was read the checker infers which
+// transaction depends on which, and reports every cycle in that graph as an anomaly.
+// Reads of a list show the order of its appends. A register only shows it where a
+// transaction read a value and then wrote the key, so its overwrites elsewhere go unseen.
+pub struct TxnSerializability {
+    // also order transactions by real time, which checks for strict serializability
+    pub realtime: bool,
+}
+
+impl TxnSerializability {
+    pub fn new() -> Self {
+        TxnSerializability { realtime: false }
+    }
+
+    pub fn strict() -> Self {
+        TxnSerializability { realtime: true }
+    }
+}
+
+impl Default for TxnSerializability {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Why one transaction has to come before another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dep {
+    // it overwrote (or appended after) the other's write
+    Ww,
+    // it read the other's write
+    Wr,
+    // the other overwrote what it read
+    Rw,
+    // it completed before the other was invoked
+    Realtime,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnomalyKind {
+    // a transaction read the write of a failed transaction
+    G1a,
+    // a transaction read a value its writer overwrote itself
+    G1b,
+    // two reads of a list disagree on the order of its appends
+    IncompatibleOrder,
+    // cycle of ww dependencies
+    G0,
+    // cycle of ww and wr dependencies
+    G1c,
+    // cycle with exactly one rw dependency
+    GSingle,
+    // cycle with more than one rw dependency
+    G2,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    // the cycle needs realtime dependencies, so the history would be serializable but
+    // isn't strictly serializable
+    pub realtime: bool,
+    // transactions of the cycle, each with its dependency on the next one. The last
+    // one depends on the first.
+    pub cycle: Vec<(String, Dep)>,
+    // what went wrong, for anomalies that are not cycles
+    pub detail: String,
+}
+
+impl fmt::Display for Dep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dep::Ww => write!(f, "ww"),
+            Dep::Wr => write!(f, "wr"),
+            Dep::Rw => write!(f, "rw"),
+            Dep::Realtime => write!(f, "realtime"),
+        }
+    }
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnomalyKind::G1a => write!(f, "G1a"),
+            AnomalyKind::G1b => write!(f, "G1b"),
+            AnomalyKind::IncompatibleOrder => write!(f, "incompatible-order"),
+            AnomalyKind::G0 => write!(f, "G0"),
+            AnomalyKind::G1c => write!(f, "G1c"),
+            AnomalyKind::GSingle => write!(f, "G-single"),
+            AnomalyKind::G2 => write!(f, "G2"),
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if self.realtime {
+            write!(f, "-realtime")?;
+        }
+        if self.cycle.is_empty() {
+            return write!(f, ": {}", self.detail);
+        }
+        write!(f, ":")?;
+        for (txn, dep) in &self.cycle {
+            write!(f, " {} -{}->", txn, dep)?;
+        }
+        write!(f, " {}", self.cycle[0].0)
+    }
+}
+
+struct Mop {
+    f: String,
+    // keys and values are compared by their JSON text
+    key: String,
+    value: Value,
+}
+
+fn mops(value: &Value) -> Vec<Mop> {
+    let Some(txn) = value.get("txn").and_then(Value::as_array) else {
+        return vec![];
+    };
+    txn.iter()
+        .filter_map(|mop| {
+            let mop = mop.as_array()?;
+            Some(Mop {
+                f: mop.first()?.as_str()?.to_string(),
+                key: mop.get(1)?.to_string(),
+                value: mop.get(2).cloned().unwrap_or(Value::Null),
+            })
+        })
+        .collect()
+}
+
+fn is_write(mop: &Mop) -> bool {
+    mop.f == "append" || mop.f == "w"
+}
+
+struct Txn<'a> {
+    span: &'a OpSpan<'a>,
+    mops: Vec<Mop>,
+    // reads of transactions that may not have happened tell nothing
+    ok: bool,
+}
+
+impl Txn<'_> {
+    fn describe(&self) -> String {
+        let txn = self.span.value().get("txn").cloned().unwrap_or_default();
+        format!(
+            "{}#{} {}",
+            self.span.invoke.client, self.span.invoke.msg_id, txn
+        )
+    }
+}
+
+struct Graph {
+    edges: Vec<Vec<(usize, Dep)>>,
+}
+
+impl Graph {
+    fn add(&mut self, from: usize, to: usize, dep: Dep) {
+        if from != to && !self.edges[from].contains(&(to, dep)) {
+            self.edges[from].push((to, dep));
+        }
+    }
+
+    // Strongly connected component of every transaction, only transactions in the
+    // same component can be on a cycle together.
+    fn components(&self) -> Vec<usize> {
+        let n = self.edges.len();
+        let mut visited = vec![false; n];
+        let mut finished = vec![];
+        for start in 0..n {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            let mut stack = vec![(start, 0)];
+            while let Some((node, i)) = stack.pop() {
+                if let Some(&(next, _)) = self.edges[node].get(i) {
+                    stack.push((node, i + 1));
+                    if !visited[next] {
+                        visited[next] = true;
+                        stack.push((next, 0));
+                    }
+                } else {
+                    finished.push(node);
+                }
+            }
+        }
+
+        let mut reverse = vec![vec![]; n];
+        for (from, edges) in self.edges.iter().enumerate() {
+            for (to, _) in edges {
+                reverse[*to].push(from);
+            }
+        }
+        let mut component = vec![usize::MAX; n];
+        for (c, &start) in finished.iter().rev().enumerate() {
+            if component[start] != usize::MAX {
+                continue;
+            }
+            component[start] = c;
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                for &prev in &reverse[node] {
+                    if component[prev] == usize::MAX {
+                        component[prev] = c;
+                        stack.push(prev);
+                    }
+                }
+            }
+        }
+        component
+    }
+
+    // Shortest path from one transaction to another over the allowed dependencies,
+    // as the transactions along the way with the dependency each one is left by.
+    fn path(
+        &self,
+        from: usize,
+        to: usize,
+        allowed: &[Dep],
+        component: &[usize],
+    ) -> Option<Vec<(usize, Dep)>> {
+        let mut parent = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(node) = queue.pop_front() {
+            if node == to {
+                let mut steps = vec![];
+                let mut at = to;
+                while at != from {
+                    let (prev, dep) = parent[&at];
+                    steps.push((prev, dep));
+                    at = prev;
+                }
+                steps.reverse();
+                return Some(steps);
+            }
+            for &(next, dep) in &self.edges[node] {
+                if allowed.contains(&dep)
+                    && component[next] == component[from]
+                    && next != from
+                    && !parent.contains_key(&next)
+                {
+                    parent.insert(next, (node, dep));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+fn cycle_kind(cycle: &[(usize, Dep)]) -> AnomalyKind {
+    let count = |dep| cycle.iter().filter(|(_, d)| *d == dep).count();
+    match count(Dep::Rw) {
+        0 if count(Dep::Wr) == 0 => AnomalyKind::G0,
+        0 => AnomalyKind::G1c,
+        1 => AnomalyKind::GSingle,
+        _ => AnomalyKind::G2,
+    }
+}
+
+impl TxnSerializability {
+    pub fn anomalies(&self, history: &History) -> Vec<Anomaly> {
+        let spans = op_spans(history);
+        let mut anomalies = vec![];
+        let anomaly = |kind, detail: String| Anomaly {
+            kind,
+            realtime: false,
+            cycle: vec![],
+            detail,
+        };
+
+        let mut txns = vec![];
+        let mut failed_writes = HashSet::new();
+        for span in spans.iter().filter(|s| s.f() == "txn") {
+            match span.op_type() {
+                OpType::Fail => {
+                    for mop in mops(span.value()).into_iter().filter(is_write) {
+                        failed_writes.insert((mop.key, mop.value.to_string()));
+                    }
+                }
+                op_type => txns.push(Txn {
+                    span,
+                    mops: mops(span.value()),
+                    ok: op_type == OpType::Ok,
+                }),
+            }
+        }
+
+        // (key, value) -> transaction that wrote it
+        let mut writers: HashMap<(String, String), usize> = HashMap::new();
+        // (key, value) -> transaction that wrote it and then wrote the key again
+        let mut intermediate: HashMap<(String, String), usize> = HashMap::new();
+        let mut writers_of: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, txn) in txns.iter().enumerate() {
+            let mut last_write: HashMap<&str, String> = HashMap::new();
+            for mop in txn.mops.iter().filter(|m| is_write(m)) {
+                let value = mop.value.to_string();
+                writers.insert((mop.key.clone(), value.clone()), i);
+                writers_of.entry(&mop.key).or_default().push(i);
+                if let Some(previous) = last_write.insert(&mop.key, value) {
+                    intermediate.insert((mop.key.clone(), previous), i);
+                }
+            }
+        }
+
+        let mut graph = Graph {
+            edges: vec![vec![]; txns.len()],
+        };
+        let mut list_reads: BTreeMap<&str, Vec<(usize, Vec<String>)>> = BTreeMap::new();
+        // (key, value) -> transactions that read it from a register
+        let mut register_reads: HashMap<(String, String), Vec<usize>> = HashMap::new();
+        for (i, txn) in txns.iter().enumerate().filter(|(_, t)| t.ok) {
+            for mop in txn.mops.iter().filter(|m| m.f == "r") {
+                let read: Vec<String> = match &mop.value {
+                    Value::Array(list) => list.iter().map(Value::to_string).collect(),
+                    Value::Null => vec![],
+                    value => vec![value.to_string()],
+                };
+                for value in &read {
+                    if failed_writes.contains(&(mop.key.clone(), value.clone())) {
+                        anomalies.push(anomaly(
+                            AnomalyKind::G1a,
+                            format!("{} read {} of a failed txn", txn.describe(), value),
+                        ));
+                    }
+                }
+                if let Some(last) = read.last()
+                    && let Some(&writer) = intermediate.get(&(mop.key.clone(), last.clone()))
+                    && writer != i
+                {
+                    anomalies.push(anomaly(
+                        AnomalyKind::G1b,
+                        format!(
+                            "{} read {}, which {} overwrote",
+                            txn.describe(),
+                            last,
+                            txns[writer].describe()
+                        ),
+                    ));
+                }
+
+                match &mop.value {
+                    Value::Array(_) => list_reads.entry(&mop.key).or_default().push((i, read)),
+                    // the initial state of a register comes before every write of it
+                    Value::Null => {
+                        for &writer in writers_of.get(mop.key.as_str()).into_iter().flatten() {
+                            graph.add(i, writer, Dep::Rw);
+                        }
+                    }
+                    value => {
+                        let version = (mop.key.clone(), value.to_string());
+                        if let Some(&writer) = writers.get(&version) {
+                            graph.add(writer, i, Dep::Wr);
+                        }
+                        register_reads.entry(version).or_default().push(i);
+                    }
+                }
+            }
+        }
+
+        // A transaction that read a register and then wrote it put its write right after
+        // the version it read: it overwrote that version's writer, and everyone else who
+        // read the version read it before the overwrite.
+        for (i, txn) in txns.iter().enumerate().filter(|(_, t)| t.ok) {
+            let mut read: HashMap<&str, &Value> = HashMap::new();
+            let mut written = HashSet::new();
+            for mop in &txn.mops {
+                match mop.f.as_str() {
+                    "r" if !written.contains(mop.key.as_str()) => {
+                        read.insert(&mop.key, &mop.value);
+                    }
+                    "w" if written.insert(mop.key.as_str()) => {
+                        let Some(value) = read.get(mop.key.as_str()) else {
+                            continue;
+                        };
+                        let version = (mop.key.clone(), value.to_string());
+                        if let Some(&writer) = writers.get(&version) {
+                            graph.add(writer, i, Dep::Ww);
+                        }
+                        for &reader in register_reads.get(&version).into_iter().flatten() {
+                            graph.add(reader, i, Dep::Rw);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        // Every read of a list is a prefix of the order its appends took effect in, and
+        // the longest read shows the most of that order.
+        for (key, reads) in &list_reads {
+            let longest = &reads.iter().max_by_key(|(_, read)| read.len()).unwrap().1;
+            if let Some((i, read)) = reads.iter().find(|(_, read)| !longest.starts_with(read)) {
+                anomalies.push(anomaly(
+                    AnomalyKind::IncompatibleOrder,
+                    format!(
+                        "{} read {} as [{}], but it was also read as [{}]",
+                        txns[*i].describe(),
+                        key,
+                        read.join(","),
+                        longest.join(",")
+                    ),
+                ));
+                continue;
+            }
+
+            let writer = |value: &String| writers.get(&(key.to_string(), value.clone())).copied();
+            for pair in longest.windows(2) {
+                if let (Some(a), Some(b)) = (writer(&pair[0]), writer(&pair[1])) {
+                    graph.add(a, b, Dep::Ww);
+                }
+            }
+            for (i, read) in reads {
+                if let Some(w) = read.last().and_then(writer) {
+                    graph.add(w, *i, Dep::Wr);
+                }
+                if let Some(w) = longest.get(read.len()).and_then(writer) {
+                    graph.add(*i, w, Dep::Rw);
+                }
+            }
+        }
+
+        if self.realtime {
+            add_realtime_edges(&mut graph, &txns);
+        }
+
+        let component = graph.components();
+        let mut seen = HashSet::new();
+        let searches: [(Dep, &[Dep]); 4] = [
+            (Dep::Ww, &[Dep::Ww]),
+            (Dep::Wr, &[Dep::Ww, Dep::Wr]),
+            (Dep::Rw, &[Dep::Ww, Dep::Wr]),
+            (Dep::Rw, &[Dep::Ww, Dep::Wr, Dep::Rw]),
+        ];
+        let passes: &[bool] = match self.realtime {
+            true => &[false, true],
+            false => &[false],
+        };
+        for &realtime in passes {
+            for (seed, allowed) in searches {
+                let mut allowed = allowed.to_vec();
+                if realtime {
+                    allowed.push(Dep::Realtime);
+                }
+                for (a, edges) in graph.edges.iter().enumerate() {
+                    for &(b, dep) in edges {
+                        if dep != seed || component[a] != component[b] {
+                            continue;
+                        }
+                        let Some(path) = graph.path(b, a, &allowed, &component) else {
+                            continue;
+                        };
+                        let mut cycle = vec![(a, dep)];
+                        cycle.extend(path);
+                        let members: BTreeSet<usize> = cycle.iter().map(|(t, _)| *t).collect();
+                        if !seen.insert(members) {
+                            continue;
+                        }
+                        anomalies.push(Anomaly {
+                            kind: cycle_kind(&cycle),
+                            realtime: cycle.iter().any(|(_, d)| *d == Dep::Realtime),
+                            cycle: cycle
+                                .iter()
+                                .map(|(t, d)| (txns[*t].describe(), *d))
+                                .collect(),
+                            detail: String::new(),
+                        });
+                    }
+                }
+            }
+        }
+
+        anomalies
+    }
+}
+
+// T1 -> T2 whenever T1 completed before T2 was invoked, leaving out the ones implied by
+// a transaction in between.
+fn add_realtime_edges(graph: &mut Graph, txns: &[Txn]) {
+    let mut completed: Vec<usize> = (0..txns.len()).filter(|t| txns[*t].ok).collect();
+    completed.sort_by_key(|t| txns[*t].span.completed_at());
+
+    for (t2, txn) in txns.iter().enumerate() {
+        let before = completed
+            .iter()
+            .take_while(|t1| txns[**t1].span.completed_at() < Some(txn.span.invoked_at))
+            .count();
+        // anything that completed before the latest invocation among these is implied
+        let Some(latest_invoke) = completed[..before]
+            .iter()
+            .map(|t| txns[*t].span.invoked_at)
+            .max()
+        else {
+            continue;
+        };
+        for &t1 in completed[..before].iter().rev() {
+            if txns[t1].span.completed_at() < Some(latest_invoke) {
+                break;
+            }
+            graph.add(t1, t2, Dep::Realtime);
+        }
+    }
+}
+
+impl Checker for TxnSerializability {
+    fn name(&self) -> String {
+        match self.realtime {
+            true => "strict serializability".to_string(),
+            false => "serializability".to_string(),
+        }
+    }
+
+    fn check(&self, history: &History) -> Verdict {
+        Verdict::from_problems(
+            self.anomalies(history)
+                .iter()
+                .map(|a| a.to_string())
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+fn txn_history(txns: Vec<(u64, u64, Value)>) -> History {
+    use crate::checker::op_event;
+    use crate::runtime::history::OpType::{Invoke, Ok};
+
+    let mut events = vec![];
+    for (i, (invoked_ms, completed_ms, txn)) in txns.into_iter().enumerate() {
+        let client = format!("c{}", i + 1);
+        let value = serde_json::json!({ "txn": txn });
+        events.push(op_event(
+            invoked_ms,
+            &client,
+            "n1",
+            1,
+            Invoke,
+            "txn",
+            value.clone(),
+        ));
+        events.push(op_event(completed_ms, &client, "n1", 1, Ok, "txn", value));
+    }
+    events.sort_by_key(|e| e.at);
+    History(events)
+}
+
+#[test]
+fn test_txn_cycles_are_classified() {
+    use serde_json::json;
+
+    let kinds = |txns| {
+        let history = txn_history(txns);
+        let anomalies = TxnSerializability::new().anomalies(&history);
+        anomalies.iter().map(|a| a.kind).collect::<Vec<_>>()
+    };
+
+    // the appends to x and y took effect in opposite orders
+    let g0 = vec![
+        (0, 10, json!([["append", "x", 1], ["append", "y", 2]])),
+        (0, 10, json!([["append", "x", 3], ["append", "y", 4]])),
+        (20, 30, json!([["r", "x", [1, 3]], ["r", "y", [4, 2]]])),
+    ];
+    assert_eq!(kinds(g0), vec![AnomalyKind::G0]);
+
+    // each read the other's append
+    let g1c = vec![
+        (0, 10, json!([["append", "x", 1], ["r", "y", [2]]])),
+        (0, 10, json!([["append", "y", 2], ["r", "x", [1]]])),
+    ];
+    assert_eq!(kinds(g1c), vec![AnomalyKind::G1c]);
+
+    // write skew: neither saw the other's append
+    let g2 = vec![
+        (0, 10, json!([["r", "x", []], ["append", "y", 1]])),
+        (0, 10, json!([["r", "y", []], ["append", "x", 2]])),
+        (20, 30, json!([["r", "x", [2]], ["r", "y", [1]]])),
+    ];
+    assert_eq!(kinds(g2), vec![AnomalyKind::G2]);
+
+    let incompatible = vec![
+        (0, 10, json!([["append", "x", 1]])),
+        (0, 10, json!([["append", "x", 2]])),
+        (20, 30, json!([["r", "x", [1, 2]]])),
+        (20, 30, json!([["r", "x", [2]]])),
+    ];
+    assert_eq!(kinds(incompatible), vec![AnomalyKind::IncompatibleOrder]);
+}
+
+#[test]
+fn test_stale_read_is_only_a_strict_serializability_anomaly() {
+    use serde_json::json;
+
+    // the second transaction started after the append completed, but didn't see it
+    let history = txn_history(vec![
+        (0, 10, json!([["append", "x", 1]])),
+        (20, 30, json!([["r", "x", []]])),
+        (40, 50, json!([["r", "x", [1]]])),
+    ]);
+    assert!(TxnSerializability::new().check(&history).valid);
+
+    let anomalies = TxnSerializability::strict().anomalies(&history);
+    assert_eq!(anomalies.len(), 1);
+    assert_eq!(anomalies[0].kind, AnomalyKind::GSingle);
+    assert!(anomalies[0].realtime);
+    assert!(
+        anomalies[0]
+            .to_string()
+            .starts_with("G-single-realtime: c2#1")
+    );
+}
+
+#[test]
+fn test_register_overwrites_are_inferred_from_read_then_write() {
+    use serde_json::json;
+
+    // the second transaction overwrote x=1, yet the third read x=1 along with its y
+    let history = txn_history(vec![
+        (0, 10, json!([["w", "x", 1]])),
+        (20, 30, json!([["r", "x", 1], ["w", "x", 2], ["w", "y", 2]])),
+        (20, 30, json!([["r", "x", 1], ["r", "y", 2]])),
+    ]);
+    let anomalies = TxnSerializability::new().anomalies(&history);
+    assert_eq!(anomalies.len(), 1);
+    assert_eq!(anomalies[0].kind, AnomalyKind::GSingle);
+    assert_eq!(
+        anomalies[0].to_string(),
+        format!(
+            "G-single: c3#1 {} -rw-> c2#1 {} -wr-> c3#1 {}",
+            json!([["r", "x", 1], ["r", "y", 2]]),
+            json!([["r", "x", 1], ["w", "x", 2], ["w", "y", 2]]),
+            json!([["r", "x", 1], ["r", "y", 2]]),
+        )
+    );
+}
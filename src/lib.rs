@@ -1,8 +1,15 @@
+pub mod checker;
 pub mod packet;
 pub mod runtime;
 
 mod util;
 
 pub use packet::*;
+pub use runtime::adversary::{Adversary, Attack};
+pub use runtime::end::EndCondition;
+pub use runtime::history::*;
 pub use runtime::input::*;
+pub use runtime::kv::{KvService, LIN_KV, LWW_KV, SEQ_KV};
+pub use runtime::routing::*;
+pub use runtime::workload::{Workload, WorkloadKind};
 pub use runtime::*;
@@ -1,17 +1,26 @@
-use crate::{packet::Rpc, runtime::Runtime};
+use biv::clean_leftover_containers;
 
-mod packet;
-mod runtime;
-mod util;
+// Design decisions. We can make it cli but also code.
 #[tokio::main]
-async fn main() {
-    // let packet = packet::Packet::Rpc(Rpc {
-    //     src: "hi".to_owned(),
-    //     dst: "todo!()".to_owned(),
-    //     data: "adsfas".as_bytes().to_vec(),
-    // });
-    // println!("{}", serde_json::to_string(&packet).unwrap());
-    // launch().await;
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        // biv clean [run_id]
+        Some("clean") => {
+            let removed = clean_leftover_containers(args.get(1).map(String::as_str)).await?;
+            for c in &removed {
+                println!(
+                    "removed {} (run: {}, node: {})",
+                    c.names.join(","),
+                    c.run_id.as_deref().unwrap_or("?"),
+                    c.node_name.as_deref().unwrap_or("?"),
+                );
+            }
+            println!("removed {} container(s)", removed.len());
+        }
+        _ => {
+            eprintln!("usage: biv clean [run_id]");
+        }
+    }
+    Ok(())
 }
-
-// Design decisions. We can make it cli but also code.
@@ -1,16 +1,27 @@
-use std::fmt;
+use std::{borrow::Cow, fmt};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::Value;
 use serde_with::serde_as;
 
+mod payload;
+
+pub use payload::*;
+
 pub type NodeId = String;
 
+static NO_PAYLOAD: Payload = Payload::Text(String::new());
+
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum Packet {
     Rpc(Rpc),
     Broadcast(Broadcast),
+    Multicast(Multicast),
     Init(Init),
+    Timer(Timer),
+    Tick(Tick),
+    Time(Time),
 }
 
 impl fmt::Display for Packet {
@@ -22,9 +33,22 @@ impl fmt::Display for Packet {
             Packet::Broadcast(broadcast) => {
                 write!(f, "Broadcast {{ src: {}, data: {} }}", broadcast.src, broadcast.data)
             },
+            Packet::Multicast(multicast) => {
+                write!(f, "Multicast {{ src: {}, dsts: [{}], data: {} }}", multicast.src, multicast.dsts.join(", "), multicast.data)
+            },
             Packet::Init(init) => {
                 write!(f, "Init {{ node_id: {}, data: {} }}", init.node_id, init.data)
             },
+            Packet::Timer(timer) => {
+                write!(f, "Timer {{ src: {}, after_ms: {}, data: {} }}", timer.src, timer.after_ms, timer.data)
+            },
+            Packet::Tick(tick) => {
+                write!(f, "Tick {{ node_id: {}, now_ms: {}, data: {} }}", tick.node_id, tick.now_ms, tick.data)
+            },
+            Packet::Time(time) => match time.now_ms {
+                Some(now_ms) => write!(f, "Time {{ node_id: {}, now_ms: {} }}", time.node_id, now_ms),
+                None => write!(f, "Time {{ node_id: {} }}", time.node_id),
+            },
         };
     }
 }
@@ -34,6 +58,8 @@ impl Packet {
         match self {
             Packet::Rpc(rpc) => Some(rpc.dst.clone()),
             Packet::Init(init) => Some(init.node_id.clone()),
+            Packet::Tick(tick) => Some(tick.node_id.clone()),
+            Packet::Time(time) if time.now_ms.is_some() => Some(time.node_id.clone()),
             _ => None,
         }
     }
@@ -41,14 +67,46 @@ impl Packet {
         match self {
             Packet::Rpc(rpc) => Some(rpc.src.clone()),
             Packet::Broadcast(broadcast) => Some(broadcast.src.clone()),
-            Packet::Init(_) => None,
+            Packet::Multicast(multicast) => Some(multicast.src.clone()),
+            Packet::Timer(timer) => Some(timer.src.clone()),
+            Packet::Time(time) if time.now_ms.is_none() => Some(time.node_id.clone()),
+            Packet::Init(_) | Packet::Tick(_) | Packet::Time(_) => None,
+        }
+    }
+    // The "type" the packet itself is serialized with, e.g. "tick".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Packet::Rpc(_) => "rpc",
+            Packet::Broadcast(_) => "broadcast",
+            Packet::Multicast(_) => "multicast",
+            Packet::Init(_) => "init",
+            Packet::Timer(_) => "timer",
+            Packet::Tick(_) => "tick",
+            Packet::Time(_) => "time",
         }
     }
-    pub fn data(&self) -> String {
+
+    pub fn body(&self) -> Option<Cow<'_, Value>> {
+        self.data().body()
+    }
+
+    pub fn body_type(&self) -> Option<String> {
+        self.data().body_type()
+    }
+
+    pub fn body_as<T: DeserializeOwned>(&self) -> anyhow::Result<T> {
+        self.data().body_as()
+    }
+
+    pub fn data(&self) -> &Payload {
         match self {
-            Packet::Rpc(rpc) => rpc.data.clone(),
-            Packet::Broadcast(broadcast) => broadcast.data.clone(),
-            Packet::Init(init) => init.data.clone(),
+            Packet::Rpc(rpc) => &rpc.data,
+            Packet::Broadcast(broadcast) => &broadcast.data,
+            Packet::Multicast(multicast) => &multicast.data,
+            Packet::Init(init) => &init.data,
+            Packet::Timer(timer) => &timer.data,
+            Packet::Tick(tick) => &tick.data,
+            Packet::Time(_) => &NO_PAYLOAD,
         }
     }
 }
@@ -58,19 +116,62 @@ impl Packet {
 pub struct Rpc {
     pub src: NodeId,
     pub dst: NodeId,
-    pub data: String,
+    #[serde(flatten)]
+    pub data: Payload,
 }
 
 #[serde_as]
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 pub struct Broadcast {
     pub src: NodeId,
-    pub data: String,
+    #[serde(flatten)]
+    pub data: Payload,
+}
+
+// Sent to an explicit subset of nodes, e.g. the fanout of a gossip round.
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Multicast {
+    pub src: NodeId,
+    pub dsts: Vec<NodeId>,
+    #[serde(flatten)]
+    pub data: Payload,
 }
 
 #[serde_as]
 #[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
 pub struct Init {
     pub node_id: NodeId,
-    pub data: String,
+    #[serde(flatten)]
+    pub data: Payload,
+}
+
+// Sent by a node to the harness: wake me up in after_ms, with data as the tag.
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Timer {
+    pub src: NodeId,
+    pub after_ms: u64,
+    #[serde(flatten)]
+    pub data: Payload,
+}
+
+// Sent by the harness when a node's timer fires, echoing the timer's tag.
+// now_ms is the node's (possibly skewed) clock at that moment.
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Tick {
+    pub node_id: NodeId,
+    pub now_ms: u64,
+    #[serde(flatten)]
+    pub data: Payload,
+}
+
+// A node asks the harness for the time with now_ms left out, and the harness answers
+// with the node's (possibly skewed) clock in milliseconds since the unix epoch.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub struct Time {
+    pub node_id: NodeId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub now_ms: Option<u64>,
 }
@@ -0,0 +1,274 @@
+use core::fmt;
+use std::borrow::Cow;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de, de::DeserializeOwned};
+use serde_json::Value;
+use serde_with::{base64::Base64, serde_as};
+
+// What a packet carries. On the wire it is a `data` field plus an `encoding` field
+// saying how to read it:
+//   {"data": "hello"}                          text, the encoding can be left out
+//   {"data": "aGVsbG8=", "encoding": "base64"} raw bytes
+//   {"data": {"k": 1}, "encoding": "json"}     a structured value
+// Without an encoding, anything that isn't a string is taken as JSON.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Payload {
+    Text(String),
+    Bytes(Vec<u8>),
+    Json(Value),
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Encoding {
+    Text,
+    Base64,
+    Json,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WirePayload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<Encoding>,
+    data: Value,
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+struct Base64Bytes(#[serde_as(as = "Base64")] Vec<u8>);
+
+impl Payload {
+    // Structured payload from any serializable body.
+    pub fn json<T: Serialize>(body: &T) -> anyhow::Result<Self> {
+        Ok(Payload::Json(serde_json::to_value(body)?))
+    }
+
+    // The payload as a JSON value. Text and bytes that contain JSON are parsed,
+    // so nodes that still send JSON inside a string can be inspected the same way.
+    pub fn body(&self) -> Option<Cow<'_, Value>> {
+        match self {
+            Payload::Json(value) => Some(Cow::Borrowed(value)),
+            Payload::Text(text) => serde_json::from_str(text).ok().map(Cow::Owned),
+            Payload::Bytes(bytes) => serde_json::from_slice(bytes).ok().map(Cow::Owned),
+        }
+    }
+
+    pub fn body_as<T: DeserializeOwned>(&self) -> anyhow::Result<T> {
+        let body = self
+            .body()
+            .ok_or_else(|| anyhow!("payload is not JSON: {}", self))?;
+        Ok(T::deserialize(body.as_ref())?)
+    }
+
+    // A top level field of the body, e.g. field("key").
+    pub fn field(&self, name: &str) -> Option<Value> {
+        self.body()?.get(name).cloned()
+    }
+
+    // The "type" field of the body, e.g. "write" or "read_ok".
+    pub fn body_type(&self) -> Option<String> {
+        match self.field("type")? {
+            Value::String(t) => Some(t),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Payload::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Payload::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_json(&self) -> Option<&Value> {
+        match self {
+            Payload::Json(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Payload::Text(text) => text.is_empty(),
+            Payload::Bytes(bytes) => bytes.is_empty(),
+            Payload::Json(value) => value.is_null(),
+        }
+    }
+
+    fn to_wire(&self) -> WirePayload {
+        match self {
+            Payload::Text(text) => WirePayload {
+                encoding: None,
+                data: Value::String(text.clone()),
+            },
+            Payload::Bytes(bytes) => WirePayload {
+                encoding: Some(Encoding::Base64),
+                data: serde_json::to_value(Base64Bytes(bytes.clone())).unwrap_or_default(),
+            },
+            Payload::Json(value) => WirePayload {
+                encoding: Some(Encoding::Json),
+                data: value.clone(),
+            },
+        }
+    }
+
+    fn from_wire(wire: WirePayload) -> Result<Self, String> {
+        match (wire.encoding, wire.data) {
+            (None | Some(Encoding::Text), Value::String(text)) => Ok(Payload::Text(text)),
+            (Some(Encoding::Text), other) => {
+                Err(format!("text payload is not a string: {}", other))
+            }
+            (Some(Encoding::Base64), data) => serde_json::from_value::<Base64Bytes>(data)
+                .map(|bytes| Payload::Bytes(bytes.0))
+                .map_err(|e| e.to_string()),
+            (None | Some(Encoding::Json), value) => Ok(Payload::Json(value)),
+        }
+    }
+}
+
+impl Default for Payload {
+    fn default() -> Self {
+        Payload::Text(String::new())
+    }
+}
+
+impl Serialize for Payload {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_wire().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Payload {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Payload::from_wire(WirePayload::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Payload::Text(text) => write!(f, "{}", text),
+            // readable bytes are shown as such, anything else as base64
+            Payload::Bytes(bytes) => match std::str::from_utf8(bytes) {
+                Ok(text) if !text.chars().any(char::is_control) => write!(f, "b{:?}", text),
+                _ => write!(
+                    f,
+                    "base64:{}",
+                    self.to_wire().data.as_str().unwrap_or_default()
+                ),
+            },
+            Payload::Json(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl From<&str> for Payload {
+    fn from(text: &str) -> Self {
+        Payload::Text(text.to_string())
+    }
+}
+
+impl From<String> for Payload {
+    fn from(text: String) -> Self {
+        Payload::Text(text)
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(bytes: Vec<u8>) -> Self {
+        Payload::Bytes(bytes)
+    }
+}
+
+impl From<Value> for Payload {
+    fn from(value: Value) -> Self {
+        Payload::Json(value)
+    }
+}
+
+impl From<&Value> for Payload {
+    fn from(value: &Value) -> Self {
+        Payload::Json(value.clone())
+    }
+}
+
+#[cfg(test)]
+fn round_trip(payload: Payload) -> (String, Payload) {
+    let json = serde_json::to_string(&payload).unwrap();
+    let decoded = serde_json::from_str(&json).unwrap();
+    (json, decoded)
+}
+
+#[test]
+fn test_text_payload_keeps_the_old_wire_format() {
+    let (json, decoded) = round_trip("hello".into());
+    assert_eq!(json, r#"{"data":"hello"}"#);
+    assert_eq!(decoded, Payload::Text("hello".to_string()));
+}
+
+#[test]
+fn test_bytes_payload_is_base64_on_the_wire() {
+    let (json, decoded) = round_trip(vec![0, 159, 146, 150].into());
+    assert_eq!(json, r#"{"encoding":"base64","data":"AJ+Slg=="}"#);
+    assert_eq!(decoded, Payload::Bytes(vec![0, 159, 146, 150]));
+}
+
+#[test]
+fn test_json_payload_round_trips_even_when_it_is_a_string() {
+    let (_, decoded) = round_trip(Value::String("hi".to_string()).into());
+    assert_eq!(decoded, Payload::Json(Value::String("hi".to_string())));
+
+    let implicit: Payload = serde_json::from_str(r#"{"data": {"type": "write"}}"#).unwrap();
+    assert_eq!(
+        implicit,
+        Payload::Json(serde_json::json!({"type": "write"}))
+    );
+}
+
+#[test]
+fn test_payload_display_picks_a_readable_form() {
+    assert_eq!(Payload::from("hi").to_string(), "hi");
+    assert_eq!(Payload::from(b"hi".to_vec()).to_string(), "b\"hi\"");
+    assert_eq!(Payload::from(vec![0, 1]).to_string(), "base64:AAE=");
+    assert_eq!(
+        Payload::from(serde_json::json!({"k": 1})).to_string(),
+        r#"{"k":1}"#
+    );
+}
+
+#[test]
+fn test_body_accessors() {
+    #[derive(Deserialize, Serialize, PartialEq, Debug)]
+    struct Write {
+        key: String,
+        value: u64,
+    }
+
+    let json =
+        Payload::json(&serde_json::json!({"type": "write", "key": "x", "value": 3})).unwrap();
+    let text = Payload::from(r#"{"type": "write", "key": "x", "value": 3}"#);
+    for payload in [json, text] {
+        assert_eq!(payload.body_type().as_deref(), Some("write"));
+        assert_eq!(payload.field("value"), Some(serde_json::json!(3)));
+        assert_eq!(
+            payload.body_as::<Write>().unwrap(),
+            Write {
+                key: "x".to_string(),
+                value: 3
+            }
+        );
+    }
+
+    let plain = Payload::from("not json");
+    assert!(plain.body().is_none());
+    assert!(plain.body_type().is_none());
+    assert!(plain.body_as::<Write>().is_err());
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use serde_json::Value;
+
+use crate::{
+    packet::{NodeId, Packet, Payload},
+    runtime::routing::{Delivery, Middleware},
+    util::Rng,
+};
+
+// How many earlier packets a replaying adversary picks from.
+const REPLAY_WINDOW: usize = 100;
+const SEED: u64 = 0xbad;
+
+// What a destination gets instead of the payload.
+type Equivocation = Arc<dyn Fn(&str, &Payload) -> Payload + Send + Sync>;
+
+// What an adversary does to the packets on its links.
+#[derive(Clone)]
+pub enum Attack {
+    // flips a bit of the payload. JSON that doesn't parse anymore arrives as text
+    Corrupt,
+    // delivers an earlier packet of the same link again, along with the current one
+    Replay,
+    // claims the packet comes from another node
+    Forge { src: NodeId },
+    // every destination gets what the function makes of the payload for it
+    Equivocate(Equivocation),
+}
+
+// Middleware attacking the packets on the link from src to dst. As with LinkFault, a None
+// end matches any node, so a byzantine node is Adversary::node(id, attack). Links are
+// told apart by the node that really sent a packet, not by the src it claims.
+pub struct Adversary {
+    pub src: Option<NodeId>,
+    pub dst: Option<NodeId>,
+    pub attack: Attack,
+    state: Mutex<State>,
+}
+
+struct State {
+    rng: Rng,
+    // packets seen per (from, dst) link, for replays
+    seen: HashMap<(NodeId, NodeId), VecDeque<Packet>>,
+}
+
+impl Adversary {
+    pub fn link(src: Option<NodeId>, dst: Option<NodeId>, attack: Attack) -> Self {
+        Adversary {
+            src,
+            dst,
+            attack,
+            state: Mutex::new(State {
+                rng: Rng::new(SEED),
+                seen: HashMap::new(),
+            }),
+        }
+    }
+
+    // Attacks everything the node sends.
+    pub fn node(node: impl Into<NodeId>, attack: Attack) -> Self {
+        Adversary::link(Some(node.into()), None, attack)
+    }
+
+    fn matches(&self, src: &str, dst: &str) -> bool {
+        self.src.as_deref().is_none_or(|s| s == src) && self.dst.as_deref().is_none_or(|d| d == dst)
+    }
+}
+
+impl Middleware for Adversary {
+    fn handle(&self, mut delivery: Delivery) -> Vec<Delivery> {
+        if !self.matches(&delivery.from, &delivery.dst) {
+            return vec![delivery];
+        }
+        let mut state = self.state.lock().unwrap();
+        match &self.attack {
+            Attack::Corrupt => {
+                if let Some(data) = data_mut(&mut delivery.packet) {
+                    *data = corrupt(data, &mut state.rng);
+                }
+            }
+            Attack::Replay => {
+                let State { rng, seen } = &mut *state;
+                let link = (delivery.from.clone(), delivery.dst.clone());
+                let seen = seen.entry(link).or_default();
+                let earlier = match seen.len() as u64 {
+                    0 => None,
+                    n => Some(seen[rng.below(n) as usize].clone()),
+                };
+                if seen.len() == REPLAY_WINDOW {
+                    seen.pop_front();
+                }
+                seen.push_back(delivery.packet.clone());
+                if let Some(packet) = earlier {
+                    let replay = Delivery {
+                        packet,
+                        ..delivery.clone()
+                    };
+                    return vec![delivery, replay];
+                }
+            }
+            Attack::Forge { src } => match &mut delivery.packet {
+                Packet::Rpc(rpc) => rpc.src = src.clone(),
+                Packet::Broadcast(broadcast) => broadcast.src = src.clone(),
+                Packet::Multicast(multicast) => multicast.src = src.clone(),
+                _ => {}
+            },
+            Attack::Equivocate(equivocate) => {
+                let dst = delivery.dst.clone();
+                if let Some(data) = data_mut(&mut delivery.packet) {
+                    *data = equivocate(&dst, data);
+                }
+            }
+        }
+        vec![delivery]
+    }
+}
+
+// The payload of a packet nodes send to each other.
+fn data_mut(packet: &mut Packet) -> Option<&mut Payload> {
+    match packet {
+        Packet::Rpc(rpc) => Some(&mut rpc.data),
+        Packet::Broadcast(broadcast) => Some(&mut broadcast.data),
+        Packet::Multicast(multicast) => Some(&mut multicast.data),
+        _ => None,
+    }
+}
+
+fn corrupt(data: &Payload, rng: &mut Rng) -> Payload {
+    let mut bytes = match data {
+        Payload::Text(text) => text.clone().into_bytes(),
+        Payload::Bytes(bytes) => bytes.clone(),
+        Payload::Json(value) => value.to_string().into_bytes(),
+    };
+    if bytes.is_empty() {
+        return data.clone();
+    }
+    let i = rng.below(bytes.len() as u64) as usize;
+    // one of the low 7 bits, so ascii stays ascii
+    bytes[i] ^= 1 << rng.below(7);
+    match data {
+        Payload::Bytes(_) => Payload::Bytes(bytes),
+        Payload::Text(_) => Payload::Text(String::from_utf8_lossy(&bytes).into_owned()),
+        Payload::Json(_) => match serde_json::from_slice::<Value>(&bytes) {
+            Ok(value) => Payload::Json(value),
+            Err(_) => Payload::Text(String::from_utf8_lossy(&bytes).into_owned()),
+        },
+    }
+}
+
+#[test]
+fn test_adversary_attacks_only_its_links() {
+    use crate::packet::{Broadcast, Rpc};
+    use serde_json::json;
+    use std::time::Duration;
+
+    let delivery = |src: &str, dst: &str, body: Value| Delivery {
+        from: src.to_string(),
+        dst: dst.to_string(),
+        packet: Packet::Rpc(Rpc {
+            src: src.to_string(),
+            dst: dst.to_string(),
+            data: Payload::Json(body),
+        }),
+        delay: Duration::ZERO,
+    };
+
+    let forger = Adversary::node("n1", Attack::Forge { src: "n3".into() });
+    let forged = forger.handle(delivery("n1", "n2", json!({"type": "vote"})));
+    assert_eq!(forged[0].packet.src().unwrap(), "n3");
+    assert_eq!(forged[0].from, "n1");
+    let honest = delivery("n2", "n1", json!({"type": "vote"}));
+    assert_eq!(forger.handle(honest.clone()), vec![honest]);
+
+    let replayer = Adversary::link(Some("n1".into()), Some("n2".into()), Attack::Replay);
+    assert_eq!(replayer.handle(delivery("n1", "n2", json!(1))).len(), 1);
+    let replayed = replayer.handle(delivery("n1", "n2", json!(2)));
+    assert_eq!(replayed.len(), 2);
+    assert_eq!(replayed[1].packet.body().unwrap().as_ref(), &json!(1));
+
+    let corrupter = Adversary::node("n1", Attack::Corrupt);
+    let original = delivery("n1", "n2", json!({"type": "commit", "value": 12345}));
+    let corrupted = corrupter.handle(original.clone());
+    assert_ne!(corrupted[0].packet.data(), original.packet.data());
+
+    // a byzantine leader proposing a different value to every follower
+    let liar = Adversary::node(
+        "n1",
+        Attack::Equivocate(Arc::new(|dst, _| Payload::Json(json!({"propose": dst})))),
+    );
+    let proposal = |dst: &str| Delivery {
+        from: "n1".to_string(),
+        dst: dst.to_string(),
+        packet: Packet::Broadcast(Broadcast {
+            src: "n1".to_string(),
+            data: Payload::Json(json!({"propose": "x"})),
+        }),
+        delay: Duration::ZERO,
+    };
+    for dst in ["n2", "n3"] {
+        let told = liar.handle(proposal(dst));
+        assert_eq!(told[0].packet.body().unwrap()["propose"], dst);
+    }
+}
+
+#[test]
+fn test_replays_stay_on_their_link() {
+    use crate::packet::Broadcast;
+    use serde_json::json;
+    use std::time::Duration;
+
+    let broadcast = |dst: &str, n: u64| Delivery {
+        from: "n1".to_string(),
+        dst: dst.to_string(),
+        packet: Packet::Broadcast(Broadcast {
+            src: "n1".to_string(),
+            data: Payload::Json(json!(n)),
+        }),
+        delay: Duration::ZERO,
+    };
+
+    let replayer = Adversary::node("n1", Attack::Replay);
+    // the first broadcast reaches n2 and n3, and is seen once on each link
+    assert_eq!(replayer.handle(broadcast("n2", 1)).len(), 1);
+    assert_eq!(replayer.handle(broadcast("n3", 1)).len(), 1);
+    for dst in ["n2", "n3"] {
+        let replayed = replayer.handle(broadcast(dst, 2));
+        assert_eq!(replayed.len(), 2);
+        assert_eq!(replayed[1].dst, dst);
+        assert_eq!(replayed[1].packet.body().unwrap().as_ref(), &json!(1));
+    }
+}
+
+#[test]
+fn test_link_faults_see_through_forged_srcs() {
+    use crate::{
+        packet::Rpc,
+        runtime::routing::{DefaultRouter, FaultKind, LinkFault, LinkFaults, Routing},
+    };
+
+    let partition = LinkFaults(vec![LinkFault {
+        src: Some("n1".to_string()),
+        dst: Some("n2".to_string()),
+        kind: FaultKind::Drop,
+    }]);
+    let routing = Routing {
+        router: Arc::new(DefaultRouter),
+        middleware: vec![
+            Arc::new(Adversary::node("n1", Attack::Forge { src: "n3".into() })),
+            Arc::new(partition),
+        ],
+    };
+    let rpc = Packet::Rpc(Rpc {
+        src: "n1".to_string(),
+        dst: "n2".to_string(),
+        data: "vote".into(),
+    });
+    let nodes = ["n1", "n2", "n3"].map(String::from);
+    assert!(routing.route(&"n1".to_string(), &rpc, &nodes).is_empty());
+    // what n3 itself sends still gets through
+    assert_eq!(routing.route(&"n3".to_string(), &rpc, &nodes).len(), 1);
+}
@@ -0,0 +1,145 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::{sync::mpsc, time};
+
+use crate::{
+    packet::{NodeId, Packet, Tick, Time, Timer},
+    runtime::input::Env,
+    util::ErrorLoggable,
+};
+
+// How far off a node's clock is. offset_ms is added to every time the node is told,
+// drift is how much faster (or, if negative, slower) its clock runs, e.g. 0.01 is 1% fast.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClockSkew {
+    pub offset_ms: i64,
+    pub drift: f64,
+}
+
+impl ClockSkew {
+    fn rate(&self) -> f64 {
+        // a clock running backwards or standing still makes no sense
+        (1.0 + self.drift).max(f64::EPSILON)
+    }
+
+    // Real time that passes while the node's clock advances by `local`.
+    pub fn to_real(&self, local: Duration) -> Duration {
+        local.div_f64(self.rate())
+    }
+
+    // For process nodes that read the system clock themselves. FAKETIME is picked up by
+    // libfaketime when the image preloads it, the BIV_* variables are for nodes that
+    // apply the skew on their own.
+    pub fn env(&self) -> Vec<Env> {
+        let env = |name: &str, value: String| Env {
+            name: name.to_string(),
+            value,
+        };
+        vec![
+            env(
+                "FAKETIME",
+                format!("{:+}s x{}", self.offset_ms as f64 / 1000.0, self.rate()),
+            ),
+            env("BIV_CLOCK_OFFSET_MS", self.offset_ms.to_string()),
+            env("BIV_CLOCK_DRIFT", self.drift.to_string()),
+        ]
+    }
+}
+
+// The harness clock that node clocks are derived from. It advances with tokio's clock,
+// so under a paused clock (tokio::time::pause) node clocks run in virtual time too.
+#[derive(Clone, Copy, Debug)]
+pub struct Clock {
+    epoch_ms: u64,
+    started_at: time::Instant,
+}
+
+impl Clock {
+    pub fn start() -> Self {
+        Clock {
+            epoch_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            started_at: time::Instant::now(),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    // Milliseconds since the unix epoch, as the node's clock has it.
+    pub fn now_ms(&self, skew: &ClockSkew) -> u64 {
+        let local_elapsed = self.elapsed().as_secs_f64() * skew.rate() * 1000.0;
+        (self.epoch_ms as i64 + local_elapsed as i64 + skew.offset_ms).max(0) as u64
+    }
+}
+
+// Answers the packets a node sends to the harness rather than to other nodes.
+pub fn handle_clock_packet(
+    packet: &Packet,
+    node: &NodeId,
+    input_tx: &mpsc::Sender<Packet>,
+    clock: Clock,
+    skew: ClockSkew,
+) {
+    match packet {
+        Packet::Timer(timer) => schedule_tick(timer, node.clone(), input_tx.clone(), clock, skew),
+        Packet::Time(Time { now_ms: None, .. }) => {
+            let reply = Packet::Time(Time {
+                node_id: node.clone(),
+                now_ms: Some(clock.now_ms(&skew)),
+            });
+            let input_tx = input_tx.clone();
+            tokio::spawn(async move { input_tx.send(reply).await.log_on_error() });
+        }
+        _ => {}
+    }
+}
+
+// Delivers a Tick back to the node once its timer is due by the node's own clock.
+fn schedule_tick(
+    timer: &Timer,
+    node: NodeId,
+    input_tx: mpsc::Sender<Packet>,
+    clock: Clock,
+    skew: ClockSkew,
+) {
+    let after = skew.to_real(Duration::from_millis(timer.after_ms));
+    let data = timer.data.clone();
+    tokio::spawn(async move {
+        time::sleep(after).await;
+        let tick = Packet::Tick(Tick {
+            node_id: node,
+            now_ms: clock.now_ms(&skew),
+            data,
+        });
+        input_tx.send(tick).await.log_on_error();
+    });
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_skewed_clock() {
+    let fast = ClockSkew {
+        offset_ms: -1500,
+        drift: 0.25,
+    };
+    assert_eq!(
+        fast.to_real(Duration::from_millis(500)),
+        Duration::from_millis(400)
+    );
+
+    // paused, so no time passes between the two readings but what the test advances
+    let clock = Clock::start();
+    let exact = clock.now_ms(&ClockSkew::default());
+    let skewed = clock.now_ms(&fast);
+    assert_eq!(exact - skewed, 1500);
+    time::advance(Duration::from_secs(4)).await;
+    let exact = clock.now_ms(&ClockSkew::default());
+    let skewed = clock.now_ms(&fast);
+    assert_eq!(exact - skewed, 500);
+
+    let env = fast.env();
+    assert_eq!(env[0].value, "-1.5s x1.25");
+}
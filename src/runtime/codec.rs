@@ -0,0 +1,296 @@
+use anyhow::anyhow;
+
+use crate::{
+    packet::Packet,
+    runtime::{
+        history::DecodeFailure,
+        line_decoder::{
+            BoundedLines, DEFAULT_MAX_FRAME_SIZE, Decoded, DecoderOptions, Line, LineDecoder,
+        },
+    },
+};
+
+// How packets are written to a node's stdin and read back from its stdout.
+pub trait Codec: Send + Sync {
+    fn encode(&self, packet: &Packet) -> anyhow::Result<Vec<u8>>;
+    // A fresh decoder for the stdout of one node.
+    fn decoder(&self) -> Box<dyn FrameDecoder>;
+}
+
+pub trait FrameDecoder: Send {
+    // Feeds raw stdout bytes, which may end in the middle of a frame.
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Decoded>;
+}
+
+// Newline separated JSON text. Lines that aren't packets are the node logging.
+pub struct JsonLines(pub DecoderOptions);
+
+impl Codec for JsonLines {
+    fn encode(&self, packet: &Packet) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = serde_json::to_vec(packet)?;
+        bytes.push(b'\n');
+        Ok(bytes)
+    }
+
+    fn decoder(&self) -> Box<dyn FrameDecoder> {
+        Box::new(LineSplitter {
+            decoder: LineDecoder::with_options(self.0),
+            lines: BoundedLines::new(self.0.max_frame_size),
+            max_line: self.0.max_frame_size,
+        })
+    }
+}
+
+struct LineSplitter {
+    decoder: LineDecoder,
+    lines: BoundedLines,
+    max_line: usize,
+}
+
+impl FrameDecoder for LineSplitter {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Decoded> {
+        let mut decoded = vec![];
+        for line in self.lines.feed(bytes) {
+            match line {
+                Line::Complete(line) => {
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim_end_matches('\r').to_string();
+                    decoded.extend(self.decoder.decode_line(line));
+                }
+                // the line is dropped whole, a packet cut short would only decode wrong
+                Line::Oversized(raw) => {
+                    self.decoder.clear();
+                    decoded.push(Decoded::Malformed(DecodeFailure {
+                        raw,
+                        error: format!("frame exceeds {} bytes", self.max_line),
+                    }));
+                }
+            }
+        }
+        decoded
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Format {
+    fn serialize(&self, packet: &Packet) -> anyhow::Result<Vec<u8>> {
+        Ok(match self {
+            Format::Json => serde_json::to_vec(packet)?,
+            // named, so structs become maps and the "type" tag survives
+            Format::MessagePack => rmp_serde::to_vec_named(packet)?,
+            Format::Cbor => {
+                let mut bytes = vec![];
+                ciborium::into_writer(packet, &mut bytes)?;
+                bytes
+            }
+        })
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> anyhow::Result<Packet> {
+        Ok(match self {
+            Format::Json => serde_json::from_slice(bytes)?,
+            Format::MessagePack => rmp_serde::from_slice(bytes)?,
+            Format::Cbor => ciborium::from_reader(bytes)?,
+        })
+    }
+}
+
+// Every packet is a 4 byte big endian length followed by the encoded packet.
+// There is no room for logs on stdout, so nodes have to log to stderr.
+pub struct LengthPrefixed {
+    pub format: Format,
+    pub max_frame_size: usize,
+}
+
+impl LengthPrefixed {
+    pub fn new(format: Format) -> Self {
+        LengthPrefixed {
+            format,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
+
+impl Codec for LengthPrefixed {
+    fn encode(&self, packet: &Packet) -> anyhow::Result<Vec<u8>> {
+        let payload = self.format.serialize(packet)?;
+        let len = u32::try_from(payload.len())
+            .map_err(|_| anyhow!("packet of {} bytes is too large", payload.len()))?;
+        let mut bytes = len.to_be_bytes().to_vec();
+        bytes.extend(payload);
+        Ok(bytes)
+    }
+
+    fn decoder(&self) -> Box<dyn FrameDecoder> {
+        Box::new(LengthPrefixedDecoder {
+            format: self.format,
+            max_frame_size: self.max_frame_size,
+            buffer: vec![],
+            skipping: 0,
+        })
+    }
+}
+
+struct LengthPrefixedDecoder {
+    format: Format,
+    max_frame_size: usize,
+    buffer: Vec<u8>,
+    // bytes of an oversized frame that are still to be thrown away
+    skipping: usize,
+}
+
+impl FrameDecoder for LengthPrefixedDecoder {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Decoded> {
+        self.buffer.extend_from_slice(bytes);
+        let mut decoded = vec![];
+        loop {
+            if self.skipping > 0 {
+                let skipped = self.skipping.min(self.buffer.len());
+                self.buffer.drain(..skipped);
+                self.skipping -= skipped;
+                if self.skipping > 0 {
+                    break;
+                }
+            }
+            if self.buffer.len() < 4 {
+                break;
+            }
+
+            let header: [u8; 4] = self.buffer[..4].try_into().unwrap();
+            let len = u32::from_be_bytes(header) as usize;
+            if len > self.max_frame_size {
+                decoded.push(Decoded::Malformed(DecodeFailure {
+                    raw: header.to_vec(),
+                    error: format!(
+                        "frame of {} bytes exceeds {} bytes",
+                        len, self.max_frame_size
+                    ),
+                }));
+                self.buffer.drain(..4);
+                self.skipping = len;
+                continue;
+            }
+            if self.buffer.len() < 4 + len {
+                break;
+            }
+
+            let frame: Vec<u8> = self.buffer.drain(..4 + len).skip(4).collect();
+            decoded.push(match self.format.deserialize(&frame) {
+                Ok(p) => Decoded::Packet(p),
+                Err(e) => Decoded::Malformed(DecodeFailure {
+                    raw: frame,
+                    error: e.to_string(),
+                }),
+            });
+        }
+        decoded
+    }
+}
+
+#[cfg(test)]
+fn rpc() -> Packet {
+    Packet::Rpc(crate::packet::Rpc {
+        src: "n1".to_string(),
+        dst: "n2".to_string(),
+        data: "hello".into(),
+    })
+}
+
+#[cfg(test)]
+fn feed_bytewise(decoder: &mut dyn FrameDecoder, bytes: &[u8]) -> Vec<Decoded> {
+    bytes.iter().flat_map(|b| decoder.feed(&[*b])).collect()
+}
+
+#[test]
+fn test_json_lines_round_trip_with_logs() {
+    let codec = JsonLines(DecoderOptions::default());
+    let mut bytes = b"booting\r\n".to_vec();
+    bytes.extend(codec.encode(&rpc()).unwrap());
+
+    let decoded = feed_bytewise(codec.decoder().as_mut(), &bytes);
+    assert_eq!(
+        decoded,
+        vec![Decoded::Text("booting".to_string()), Decoded::Packet(rpc())]
+    );
+}
+
+#[test]
+fn test_json_lines_drops_oversized_lines_whole() {
+    let codec = JsonLines(DecoderOptions {
+        max_frame_size: 80,
+        ..Default::default()
+    });
+    let mut bytes = format!("{{\"data\": \"{}\"}}\n", "x".repeat(100)).into_bytes();
+    bytes.extend(codec.encode(&rpc()).unwrap());
+    bytes.extend(b"booting\n");
+
+    // nothing of the long line shows up as a log, and what follows it decodes fine
+    let decoded = feed_bytewise(codec.decoder().as_mut(), &bytes);
+    match &decoded[..] {
+        [Decoded::Malformed(failure), packet, log] => {
+            assert_eq!(failure.raw.len(), 80);
+            assert_eq!(*packet, Decoded::Packet(rpc()));
+            assert_eq!(*log, Decoded::Text("booting".to_string()));
+        }
+        other => panic!("expected malformed, packet and log, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_length_prefixed_round_trip() {
+    for format in [Format::Json, Format::MessagePack, Format::Cbor] {
+        let binary = Packet::Broadcast(crate::packet::Broadcast {
+            src: "n1".to_string(),
+            data: vec![0, 1, 2, 255].into(),
+        });
+        let codec = LengthPrefixed::new(format);
+        let mut bytes = codec.encode(&rpc()).unwrap();
+        bytes.extend(codec.encode(&binary).unwrap());
+
+        let decoded = feed_bytewise(codec.decoder().as_mut(), &bytes);
+        assert_eq!(
+            decoded,
+            vec![Decoded::Packet(rpc()), Decoded::Packet(binary)],
+            "{:?}",
+            format
+        );
+    }
+}
+
+#[test]
+fn test_length_prefixed_skips_oversized_frames() {
+    let codec = LengthPrefixed {
+        format: Format::MessagePack,
+        max_frame_size: 64,
+    };
+    let mut bytes = 100u32.to_be_bytes().to_vec();
+    bytes.extend([0u8; 100]);
+    bytes.extend(
+        LengthPrefixed::new(Format::MessagePack)
+            .encode(&rpc())
+            .unwrap(),
+    );
+
+    let decoded = feed_bytewise(codec.decoder().as_mut(), &bytes);
+    assert_eq!(decoded.len(), 2);
+    assert!(matches!(decoded[0], Decoded::Malformed(_)));
+    assert_eq!(decoded[1], Decoded::Packet(rpc()));
+}
+
+#[test]
+fn test_length_prefixed_reports_garbage_payload() {
+    let codec = LengthPrefixed::new(Format::Cbor);
+    let mut bytes = 3u32.to_be_bytes().to_vec();
+    bytes.extend([0xff, 0xff, 0xff]);
+    bytes.extend(codec.encode(&rpc()).unwrap());
+
+    let decoded = codec.decoder().feed(&bytes);
+    assert!(matches!(&decoded[0], Decoded::Malformed(f) if f.raw == vec![0xff, 0xff, 0xff]));
+    assert_eq!(decoded[1], Decoded::Packet(rpc()));
+}
@@ -1,65 +1,116 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::anyhow;
 use testcontainers::core::WaitFor;
 use testcontainers::{
     ContainerAsync, GenericImage, ImageExt,
-    bollard::query_parameters::AttachContainerOptions,
+    bollard::query_parameters::{
+        AttachContainerOptions, ListContainersOptions, RemoveContainerOptions,
+    },
     runners::AsyncRunner,
 };
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::sync::mpsc;
 
 use crate::{
-    packet::Packet,
-    runtime::{input::Env, line_decoder::LineDecoder},
+    packet::{NodeId, Packet},
+    runtime::{
+        codec::Codec,
+        history::{EventKind, Log, LogStream},
+        input::Env,
+        line_decoder::{BoundedLines, DEFAULT_MAX_FRAME_SIZE, Decoded, Line},
+    },
+    util::ErrorLoggable,
 };
 
+// Every container biv starts carries these labels, so leftovers can be found again.
+pub const MANAGED_LABEL: &str = "biv.managed";
+pub const RUN_LABEL: &str = "biv.run";
+pub const NODE_LABEL: &str = "biv.node";
+
+// Everything needed to launch the container of a single node in a test run.
+#[derive(Clone)]
+pub struct NodeSpec {
+    pub image_name: &'static str,
+    pub image_tag: &'static str,
+    pub env: Vec<Env>,
+    pub node_name: NodeId,
+    pub run_id: String,
+    pub codec: Arc<dyn Codec>,
+}
+
+impl NodeSpec {
+    // Docker names are global, so the node name alone would collide with
+    // concurrent runs or with containers left behind by a crashed one.
+    pub fn container_name(&self) -> String {
+        // docker only takes [a-zA-Z0-9_.-], so everything else is escaped as _XX, along
+        // with _ itself, to keep distinct node names apart
+        let mut node_name = String::new();
+        for byte in self.node_name.bytes() {
+            match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' => {
+                    node_name.push(byte as char)
+                }
+                _ => node_name.push_str(&format!("_{:02X}", byte)),
+            }
+        }
+        format!("biv-{}-{}", self.run_id, node_name)
+    }
+
+    fn labels(&self) -> HashMap<String, String> {
+        HashMap::from([
+            (MANAGED_LABEL.to_string(), "true".to_string()),
+            (RUN_LABEL.to_string(), self.run_id.clone()),
+            (NODE_LABEL.to_string(), self.node_name.clone()),
+        ])
+    }
+}
+
 pub trait RunnableContainer
 where
     Self: Sized + Send,
 {
-    async fn launch(
-        image_name: &'static str,
-        image_tag: &'static str,
-        env: Vec<Env>,
-        node_name: String,
-    ) -> anyhow::Result<Self>;
-    fn subscribe_stdout(&self, output_tx: mpsc::Sender<Packet>);
+    async fn launch(spec: NodeSpec) -> anyhow::Result<Self>;
+    // Sends packets and log lines the node writes to stdout/stderr.
+    fn subscribe_stdout(&self, output_tx: mpsc::Sender<EventKind>);
     fn stdin_tx(&self) -> mpsc::Sender<Packet>;
+    fn container_name(&self) -> String;
     async fn stop(&self);
 }
 
 pub struct Container {
     node_name: String,
+    container_name: String,
+    codec: Arc<dyn Codec>,
     inner_container: ContainerAsync<GenericImage>,
     input_tx: mpsc::Sender<Packet>,
 }
 
 impl RunnableContainer for Container {
-    async fn launch(
-        image_name: &'static str,
-        image_tag: &'static str,
-        env: Vec<Env>,
-        node_name: String,
-    ) -> anyhow::Result<Self> {
+    async fn launch(spec: NodeSpec) -> anyhow::Result<Self> {
         //launch container from docker file path with env
+        let node_name = spec.node_name.clone();
+        let container_name = spec.container_name();
 
-        let image = GenericImage::new(image_name, image_tag)
+        let image = GenericImage::new(spec.image_name, spec.image_tag)
         .with_wait_for(WaitFor::millis(1000));
 
-        let mut container_req = image.with_container_name(&node_name).with_open_stdin(true);
-        for e in env {
+        let mut container_req = image
+            .with_container_name(&container_name)
+            .with_labels(spec.labels())
+            .with_open_stdin(true);
+        for e in spec.env {
             container_req = container_req.with_env_var(e.name, e.value);
         }
         let container = container_req
             .start()
             .await
-            .expect("Failed to start container");
+            .map_err(|e| anyhow!("Failed to start container {}: {}", container_name, e))?;
 
-        let docker_client = testcontainers::core::client::docker_client_instance()
-            .await
-            .unwrap();
+        let docker_client = testcontainers::core::client::docker_client_instance().await?;
         let result = docker_client
             .attach_container(
-                &node_name,
+                &container_name,
                 Some(AttachContainerOptions {
                     stdin: true,
                     detach_keys: None,
@@ -69,62 +120,93 @@ impl RunnableContainer for Container {
                     stderr: false,
                 }),
             )
-            .await
-            .unwrap();
+            .await?;
 
         let mut stdin = result.input;
 
         let (input_tx, mut input_rx) = tokio::sync::mpsc::channel(10);
 
         let cloned_node_name = node_name.clone();
+        let codec = spec.codec.clone();
         tokio::spawn(async move {
             while let Some(packet) = input_rx.recv().await {
-                let input_str = serde_json::to_string(&packet).unwrap() + "\n";
-                eprintln!("{} stdin: {}", cloned_node_name, input_str);
-                stdin
-                    .write_all(input_str.into_bytes().as_slice())
-                    .await
-                    .unwrap();
-                stdin.flush().await.unwrap();
+                let bytes = match codec.encode(&packet) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        eprintln!("{}: failed to encode packet: {}", cloned_node_name, e);
+                        continue;
+                    }
+                };
+                // the container is gone, nothing more can be written to it
+                let written = match stdin.write_all(&bytes).await {
+                    Ok(()) => stdin.flush().await,
+                    Err(e) => Err(e),
+                };
+                if written.is_err() {
+                    written.log_on_error();
+                    break;
+                }
             }
         });
 
         Ok(Container {
             node_name,
+            container_name,
+            codec: spec.codec,
             inner_container: container,
             input_tx,
         })
     }
 
-    fn subscribe_stdout(&self, output_tx: mpsc::Sender<Packet>) {
-        let mut stdout = self.inner_container.stdout(true).lines();
-        let node_name = self.node_name.clone();
+    fn subscribe_stdout(&self, output_tx: mpsc::Sender<EventKind>) {
+        let mut stdout = self.inner_container.stdout(true);
+        let stdout_tx = output_tx.clone();
+        let mut decoder = self.codec.decoder();
         tokio::spawn(async move {
-            let mut decoder = LineDecoder::new();
-            let output_tx = output_tx.clone();
-            while let Some(line) = stdout.next_line().await.unwrap() {
-                // eprintln!("{} stdout: {}", &node_name, line);
-                if let Some(result) = decoder.add_to_buffer(line.clone()) {
-                    match result {
-                        Ok(p) => {
-                            eprintln!("stdout packet");
-                            output_tx.send(p).await.unwrap();
-                            decoder.clear();
-                        }
-                        Err(e) => {
-                            eprintln!("Failed to decode packet: {}", e);
-                            decoder.clear();
-                        }
-                    }
+            let mut buf = vec![0; 8192];
+            while let Ok(n @ 1..) = stdout.read(&mut buf).await {
+                for decoded in decoder.feed(&buf[..n]) {
+                    let kind = match decoded {
+                        Decoded::Packet(p) => EventKind::Packet(p),
+                        Decoded::Text(line) => EventKind::Log(Log {
+                            stream: LogStream::Stdout,
+                            line,
+                        }),
+                        Decoded::Malformed(failure) => EventKind::DecodeFailure(failure),
+                    };
+                    stdout_tx.send(kind).await.log_on_error();
                 }
             }
         });
 
-        let node_name = self.node_name.clone();
-        let mut stderr = self.inner_container.stderr(true).lines();
+        let mut stderr = self.inner_container.stderr(true);
         tokio::spawn(async move {
-            while let Some(line) = stderr.next_line().await.unwrap() {
-                eprintln!("{} stderr: {}", &node_name, line);
+            // read by hand, a node that never ends its line mustn't grow a buffer forever
+            let mut lines = BoundedLines::new(DEFAULT_MAX_FRAME_SIZE);
+            let mut buf = vec![0; 8192];
+            while let Ok(n @ 1..) = stderr.read(&mut buf).await {
+                for line in lines.feed(&buf[..n]) {
+                    let line = match line {
+                        Line::Complete(line) => String::from_utf8_lossy(&line).into_owned(),
+                        Line::Oversized(line) => format!(
+                            "{} [cut at {} bytes]",
+                            String::from_utf8_lossy(&line),
+                            DEFAULT_MAX_FRAME_SIZE
+                        ),
+                    };
+                    let log = Log {
+                        stream: LogStream::Stderr,
+                        line: line.trim_end_matches('\r').to_string(),
+                    };
+                    output_tx.send(EventKind::Log(log)).await.log_on_error();
+                }
+            }
+            if let Some(line) = lines.rest() {
+                let log = Log {
+                    stream: LogStream::Stderr,
+                    line: String::from_utf8_lossy(&line).into_owned(),
+                };
+                output_tx.send(EventKind::Log(log)).await.log_on_error();
             }
         });
     }
@@ -133,18 +215,87 @@ impl RunnableContainer for Container {
         self.input_tx.clone()
     }
 
+    fn container_name(&self) -> String {
+        self.container_name.clone()
+    }
+
     async fn stop(&self) {
-        self.inner_container
-            .stop()
-            .await
-            .expect("Stop should be done without error.")
+        if let Err(e) = self.inner_container.stop().await {
+            eprintln!("{}: failed to stop container: {}", self.node_name, e);
+        }
     }
 }
 
+// A container left behind by a biv run, e.g. because the run crashed.
+#[derive(Debug)]
+pub struct LeftoverContainer {
+    pub id: String,
+    pub names: Vec<String>,
+    pub run_id: Option<String>,
+    pub node_name: Option<NodeId>,
+}
+
+// Removes every container labelled by biv, or only the ones of the given run.
+pub async fn clean_leftover_containers(
+    run_id: Option<&str>,
+) -> anyhow::Result<Vec<LeftoverContainer>> {
+    let docker_client = testcontainers::core::client::docker_client_instance().await?;
+
+    let label_filter = match run_id {
+        Some(run_id) => format!("{}={}", RUN_LABEL, run_id),
+        None => format!("{}=true", MANAGED_LABEL),
+    };
+    let summaries = docker_client
+        .list_containers(Some(ListContainersOptions {
+            all: true,
+            filters: Some(HashMap::from([("label".to_string(), vec![label_filter])])),
+            ..Default::default()
+        }))
+        .await?;
+
+    let mut removed = vec![];
+    for summary in summaries {
+        let Some(id) = summary.id else {
+            continue;
+        };
+        docker_client
+            .remove_container(
+                &id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await?;
+
+        let labels = summary.labels.unwrap_or_default();
+        removed.push(LeftoverContainer {
+            id,
+            names: summary.names.unwrap_or_default(),
+            run_id: labels.get(RUN_LABEL).cloned(),
+            node_name: labels.get(NODE_LABEL).cloned(),
+        });
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+use crate::runtime::history::DecodeFailure;
+
+// Names of the mock containers that have been stopped, so tests can check for cleanup.
+#[cfg(test)]
+pub static STOPPED_MOCKS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(vec![]);
+
+// Mock nodes with this prefix fail to launch.
+#[cfg(test)]
+pub const UNLAUNCHABLE_MOCK_PREFIX: &str = "unlaunchable";
+
 #[cfg(test)]
 pub struct MockContainer {
     pub node_name: String,
     pub expected_stdout_packets: Option<Vec<Packet>>,
+    pub expected_logs: Option<Vec<Log>>,
+    pub expected_decode_failures: Option<Vec<DecodeFailure>>,
     pub expected_stdin: Option<mpsc::Sender<Packet>>,
 }
 
@@ -154,6 +305,8 @@ impl MockContainer {
         MockContainer {
             node_name,
             expected_stdout_packets: None,
+            expected_logs: None,
+            expected_decode_failures: None,
             expected_stdin: None,
         }
     }
@@ -161,18 +314,26 @@ impl MockContainer {
 
 #[cfg(test)]
 impl RunnableContainer for MockContainer {
-    async fn launch(
-        _image_name: &'static str,
-        _image_tag: &'static str,
-        _env: Vec<Env>,
-        node_name: String,
-    ) -> anyhow::Result<Self> {
-        Ok(MockContainer::new(node_name))
+    async fn launch(spec: NodeSpec) -> anyhow::Result<Self> {
+        if spec.node_name.starts_with(UNLAUNCHABLE_MOCK_PREFIX) {
+            return Err(anyhow!("{} refused to launch", spec.node_name));
+        }
+        Ok(MockContainer::new(spec.node_name))
     }
 
-    fn subscribe_stdout(&self, output_tx: mpsc::Sender<Packet>) {
+    fn subscribe_stdout(&self, output_tx: mpsc::Sender<EventKind>) {
         for packet in &self.expected_stdout_packets.clone().unwrap_or(vec![]) {
-            output_tx.try_send(packet.clone()).unwrap();
+            output_tx
+                .try_send(EventKind::Packet(packet.clone()))
+                .unwrap();
+        }
+        for log in &self.expected_logs.clone().unwrap_or(vec![]) {
+            output_tx.try_send(EventKind::Log(log.clone())).unwrap();
+        }
+        for failure in &self.expected_decode_failures.clone().unwrap_or(vec![]) {
+            output_tx
+                .try_send(EventKind::DecodeFailure(failure.clone()))
+                .unwrap();
         }
     }
 
@@ -186,5 +347,11 @@ impl RunnableContainer for MockContainer {
         }
     }
 
-    async fn stop(&self) {}
+    fn container_name(&self) -> String {
+        self.node_name.clone()
+    }
+
+    async fn stop(&self) {
+        STOPPED_MOCKS.lock().unwrap().push(self.node_name.clone());
+    }
 }
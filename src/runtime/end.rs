@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{
+    checker::Checker,
+    runtime::history::{Event, EventKind, History},
+};
+
+// When a run stops, the first condition to be met ends it. A test without any stops
+// once nothing happened for end_delay_secs.
+pub enum EndCondition {
+    // time since the nodes were interconnected
+    After(Duration),
+    // checked on the history after every `every` events, the whole history each time
+    CheckerPasses {
+        checker: Box<dyn Checker + Send>,
+        every: usize,
+    },
+    Events(usize),
+    // nothing happened for idle, not counting packets of the ignored kinds or body
+    // types, e.g. "tick" or "heartbeat"
+    Quiescent {
+        idle: Duration,
+        ignore: Vec<String>,
+    },
+}
+
+// Events between two checks of a CheckerPasses condition when the test doesn't say.
+const CHECK_EVERY: usize = 100;
+
+impl EndCondition {
+    pub fn checker_passes(checker: impl Checker + Send + 'static) -> Self {
+        EndCondition::CheckerPasses {
+            checker: Box::new(checker),
+            every: CHECK_EVERY,
+        }
+    }
+
+    pub fn quiescent(idle: Duration) -> Self {
+        EndCondition::Quiescent {
+            idle,
+            ignore: vec![],
+        }
+    }
+}
+
+pub(crate) struct EndWatch {
+    conditions: Vec<EndCondition>,
+    // the test's max_duration, which ends the run no matter what
+    max_duration: Option<Duration>,
+    started: Instant,
+    // per condition, when it last saw something happen
+    last_activity: Vec<Instant>,
+}
+
+impl EndWatch {
+    pub(crate) fn new(conditions: Vec<EndCondition>, max_duration: Option<Duration>) -> Self {
+        let started = Instant::now();
+        EndWatch {
+            last_activity: vec![started; conditions.len()],
+            conditions,
+            max_duration,
+            started,
+        }
+    }
+
+    // When the run ends unless an event ends it first. None waits for the nodes to stop.
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        self.conditions
+            .iter()
+            .zip(&self.last_activity)
+            .filter_map(|(condition, last_activity)| match condition {
+                EndCondition::After(limit) => Some(self.started + *limit),
+                EndCondition::Quiescent { idle, .. } => Some(*last_activity + *idle),
+                EndCondition::CheckerPasses { .. } | EndCondition::Events(_) => None,
+            })
+            .chain(self.max_duration.map(|limit| self.started + limit))
+            .min()
+    }
+
+    // The max_duration, once the run went past it.
+    pub(crate) fn timed_out(&self) -> Option<Duration> {
+        self.max_duration
+            .filter(|limit| Instant::now() >= self.started + *limit)
+    }
+
+    // Whether the run ends with the event, which is already the last one of the history.
+    pub(crate) fn observe(&mut self, event: &Event, history: &History) -> bool {
+        let now = Instant::now();
+        let mut ended = false;
+        for (condition, last_activity) in self.conditions.iter().zip(&mut self.last_activity) {
+            match condition {
+                EndCondition::After(limit) => ended |= now >= self.started + *limit,
+                EndCondition::CheckerPasses { checker, every } => {
+                    ended |= history.0.len().is_multiple_of((*every).max(1))
+                        && checker.check(history).valid
+                }
+                EndCondition::Events(n) => ended |= history.0.len() >= *n,
+                EndCondition::Quiescent { ignore, .. } => {
+                    if !is_ignored(event, ignore) {
+                        *last_activity = now;
+                    }
+                }
+            }
+        }
+        ended
+    }
+}
+
+fn is_ignored(event: &Event, ignore: &[String]) -> bool {
+    let EventKind::Packet(packet) = &event.kind else {
+        return false;
+    };
+    ignore.iter().any(|ignored| ignored == packet.kind())
+        || packet
+            .body_type()
+            .is_some_and(|body_type| ignore.contains(&body_type))
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_quiescence_ignores_heartbeats() {
+    use crate::packet::{Packet, Payload, Rpc, Tick};
+    use serde_json::json;
+
+    let packet = |body_type: &str| {
+        let packet = match body_type {
+            "tick" => Packet::Tick(Tick {
+                node_id: "n1".to_string(),
+                now_ms: 0,
+                data: Payload::Json(json!({})),
+            }),
+            _ => Packet::Rpc(Rpc {
+                src: "n1".to_string(),
+                dst: "n2".to_string(),
+                data: Payload::Json(json!({ "type": body_type })),
+            }),
+        };
+        Event {
+            at: Duration::ZERO,
+            node: "n1".to_string(),
+            kind: EventKind::Packet(packet),
+        }
+    };
+    let mut watch = EndWatch::new(
+        vec![
+            EndCondition::Quiescent {
+                idle: Duration::from_secs(1),
+                ignore: vec!["heartbeat".to_string(), "tick".to_string()],
+            },
+            EndCondition::Events(4),
+        ],
+        None,
+    );
+    let started = Instant::now();
+    let mut history = History::default();
+    // only the write keeps the run going, the fourth event ends it
+    for (after_ms, body_type, ends) in [
+        (500, "heartbeat", false),
+        (300, "write", false),
+        (0, "tick", false),
+        (0, "heartbeat", true),
+    ] {
+        tokio::time::advance(Duration::from_millis(after_ms)).await;
+        history.0.push(packet(body_type));
+        assert_eq!(watch.observe(history.0.last().unwrap(), &history), ends);
+    }
+    assert_eq!(
+        watch.deadline(),
+        Some(started + Duration::from_millis(1800))
+    );
+}
+
+#[tokio::test]
+async fn test_checker_passes_is_checked_every_few_events() {
+    use crate::{
+        checker::Verdict,
+        runtime::history::{Log, LogStream},
+    };
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    // passes once the history has 5 events
+    struct Long(Arc<AtomicUsize>);
+    impl Checker for Long {
+        fn name(&self) -> String {
+            "long".to_string()
+        }
+        fn check(&self, history: &History) -> Verdict {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            match history.0.len() >= 5 {
+                true => Verdict::valid(),
+                false => Verdict::from_problems(vec!["too short".to_string()]),
+            }
+        }
+    }
+
+    let checks = Arc::new(AtomicUsize::new(0));
+    let condition = EndCondition::CheckerPasses {
+        checker: Box::new(Long(checks.clone())),
+        every: 3,
+    };
+    let mut watch = EndWatch::new(vec![condition], None);
+    let mut history = History::default();
+    let mut ended_at = None;
+    while ended_at.is_none() {
+        history.0.push(Event {
+            at: Duration::ZERO,
+            node: "n1".to_string(),
+            kind: EventKind::Log(Log {
+                stream: LogStream::Stdout,
+                line: "working".to_string(),
+            }),
+        });
+        if watch.observe(history.0.last().unwrap(), &history) {
+            ended_at = Some(history.0.len());
+        }
+    }
+    assert_eq!(ended_at, Some(6));
+    assert_eq!(checks.load(Ordering::Relaxed), 2);
+}
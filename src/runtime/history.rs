@@ -0,0 +1,194 @@
+use core::fmt;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::packet::{NodeId, Packet};
+
+// Everything that happened during a test, in the order the runtime observed it.
+#[derive(Debug, Default)]
+pub struct History(pub Vec<Event>);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    // time since the nodes were interconnected
+    pub at: Duration,
+    // node the event originated from
+    pub node: NodeId,
+    pub kind: EventKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EventKind {
+    Packet(Packet),
+    Log(Log),
+    DecodeFailure(DecodeFailure),
+    // invocation or completion of a workload client's operation
+    Op(Op),
+    // an invariant failed on the event before this one, which ended the run
+    Violation(Violation),
+    // the run hit the test's max_duration and was cut off here
+    TimedOut(Duration),
+}
+
+// A line a node wrote that was not part of a packet.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Log {
+    pub stream: LogStream,
+    pub line: String,
+}
+
+// Output that looked like a packet but could not be decoded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodeFailure {
+    pub raw: Vec<u8>,
+    pub error: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub invariant: String,
+    pub error: String,
+}
+
+// An operation of a workload client, jepsen style: every operation is recorded once when
+// it is invoked and once more when it completes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Op {
+    pub client: NodeId,
+    // node the client sent the operation to
+    pub node: NodeId,
+    pub msg_id: u64,
+    pub op_type: OpType,
+    // what the operation does, e.g. "read", "cas" or "transfer"
+    pub f: String,
+    // the request body without type and msg_id. Completions also carry the fields of the
+    // reply, e.g. the "value" of a read, or the "code" and "text" of an error.
+    pub value: Value,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpType {
+    Invoke,
+    // the operation took effect
+    Ok,
+    // the operation certainly did not take effect
+    Fail,
+    // no idea whether it took effect, e.g. the reply never came
+    Info,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+impl History {
+    pub fn packets(&self) -> impl Iterator<Item = (&Event, &Packet)> {
+        self.0.iter().filter_map(|e| match &e.kind {
+            EventKind::Packet(p) => Some((e, p)),
+            _ => None,
+        })
+    }
+
+    pub fn logs(&self) -> impl Iterator<Item = (&Event, &Log)> {
+        self.0.iter().filter_map(|e| match &e.kind {
+            EventKind::Log(l) => Some((e, l)),
+            _ => None,
+        })
+    }
+
+    pub fn decode_failures(&self) -> impl Iterator<Item = (&Event, &DecodeFailure)> {
+        self.0.iter().filter_map(|e| match &e.kind {
+            EventKind::DecodeFailure(d) => Some((e, d)),
+            _ => None,
+        })
+    }
+
+    pub fn ops(&self) -> impl Iterator<Item = (&Event, &Op)> {
+        self.0.iter().filter_map(|e| match &e.kind {
+            EventKind::Op(o) => Some((e, o)),
+            _ => None,
+        })
+    }
+
+    pub fn logs_of<'a>(&'a self, node: &'a str) -> impl Iterator<Item = &'a Log> {
+        self.logs()
+            .filter(move |(e, _)| e.node == node)
+            .map(|(_, l)| l)
+    }
+}
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut str = String::new();
+        str.push_str("History(\n");
+        for v in &self.0 {
+            str.push_str(format!("{},\n", v).as_str());
+        }
+        str.push(')');
+        f.write_str(&str)
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[+{:.3}s] {} {}",
+            self.at.as_secs_f64(),
+            self.node,
+            self.kind
+        )
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventKind::Packet(p) => write!(f, "{}", p),
+            EventKind::Log(l) => write!(f, "{}", l),
+            EventKind::DecodeFailure(d) => write!(f, "{}", d),
+            EventKind::Op(o) => write!(f, "{}", o),
+            EventKind::Violation(v) => write!(f, "{}", v),
+            EventKind::TimedOut(after) => write!(f, "timed out after {:?}", after),
+        }
+    }
+}
+
+impl fmt::Display for Log {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.stream {
+            LogStream::Stdout => write!(f, "stdout: {}", self.line),
+            LogStream::Stderr => write!(f, "stderr: {}", self.line),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Op {{ {:?} {} #{} on {}: {} }}",
+            self.op_type, self.f, self.msg_id, self.node, self.value
+        )
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Violation {{ {}: {} }}", self.invariant, self.error)
+    }
+}
+
+impl fmt::Display for DecodeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "DecodeFailure {{ error: {}, raw: {:?} }}",
+            self.error,
+            String::from_utf8_lossy(&self.raw)
+        )
+    }
+}
@@ -1,18 +1,84 @@
-use core::fmt;
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
 
-use crate::packet::{NodeId, Packet};
+use crate::{
+    checker::Invariant,
+    packet::{NodeId, Payload},
+    runtime::{
+        clock::ClockSkew,
+        codec::{Codec, JsonLines},
+        end::EndCondition,
+        history::{Event, History},
+        kv::KvService,
+        line_decoder::DecoderOptions,
+        routing::{LinkFault, Middleware, Router},
+        workload::Workload,
+    },
+};
 
+#[derive(Default)]
 pub struct Test {
     pub nodes: Vec<NodeId>,
 
     //input
-    pub input: HashMap<NodeId, Vec<String>>,
+    pub input: HashMap<NodeId, Vec<Payload>>,
+    // clients generating operations against the nodes while the test runs
+    pub workloads: Vec<Workload>,
 
     pub image_name: &'static str,
     pub image_tag: &'static str,
     pub env: Vec<Env>,
     pub end_delay_secs: u64,
+    // when to stop, instead of after end_delay_secs without any event
+    pub end_conditions: Vec<EndCondition>,
+    // hard limit on how long the nodes run, whatever they do. Unlike EndCondition::After,
+    // hitting it fails the test with Status::TimedOut
+    pub max_duration: Option<Duration>,
+
+    // applied per destination, so a broadcast or multicast can reach only some nodes
+    pub faults: Vec<LinkFault>,
+    // where packets go, DefaultRouter if not set
+    pub router: Option<Arc<dyn Router>>,
+    // applied to every delivery in order, before the faults
+    pub middleware: Vec<Arc<dyn Middleware>>,
+    // nodes whose clocks are off
+    pub clock_skew: HashMap<NodeId, ClockSkew>,
+    // key-value stores hosted by the harness, reachable at their reserved node ids
+    pub services: Vec<KvService>,
+
+    // how many nodes may be starting at the same time. None launches all of them at once
+    pub launch_concurrency: Option<usize>,
+
+    // when set, everything a node logs also goes to <log_dir>/<node>.log
+    pub log_dir: Option<PathBuf>,
+
+    // how packets are framed on the nodes' stdout, for nodes without their own codec
+    pub decoder: DecoderOptions,
+    // per node codec, e.g. length prefixed MessagePack for high-throughput nodes
+    pub codecs: HashMap<NodeId, Arc<dyn Codec>>,
+    // a node writing output that can't be decoded fails the test
+    pub strict_decoding: bool,
+
+    // checked on every event while the test runs, the first one to fail ends it
+    pub invariants: Vec<Box<dyn Invariant>>,
+    // how many events before a failed invariant the report keeps, 20 if not set
+    pub snapshot_events: Option<usize>,
+}
+
+impl Test {
+    pub fn env_of(&self, node: &str) -> Vec<Env> {
+        let mut env = self.env.clone();
+        if let Some(skew) = self.clock_skew.get(node) {
+            env.extend(skew.env());
+        }
+        env
+    }
+
+    pub fn codec_of(&self, node: &str) -> Arc<dyn Codec> {
+        match self.codecs.get(node) {
+            Some(codec) => codec.clone(),
+            None => Arc::new(JsonLines(self.decoder)),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -22,18 +88,52 @@ pub struct Env {
 }
 
 #[derive(Debug)]
-pub struct History(pub Vec<Packet>);
-
-impl fmt::Display for History {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut str = String::new();
-        str.push_str("History(\n");
-        for v in self.0.clone() {
-            str.push_str(format!("{},\n", v).as_str());
-        }
-        str.push_str(")");
-        f.write_str(&str)
+pub struct Report {
+    pub run_id: String,
+    pub status: Status,
+    pub history: History,
+    pub startup: StartupTiming,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    Completed,
+    // a node wrote garbage while strict_decoding was on
+    DecodeFailed {
+        node: NodeId,
+        error: String,
+    },
+    // the run was cut short, snapshot ends with the event the invariant failed on
+    InvariantViolated {
+        invariant: String,
+        node: NodeId,
+        error: String,
+        snapshot: Vec<Event>,
+    },
+    // the run hit max_duration, the history is what was gathered until then
+    TimedOut {
+        after: Duration,
+    },
+}
+
+impl Status {
+    // The run was cut short by a violated invariant, max_duration or, with
+    // strict_decoding, garbage. Its nodes may well be busy still.
+    pub fn is_aborted(&self) -> bool {
+        matches!(
+            self,
+            Status::DecodeFailed { .. }
+                | Status::InvariantViolated { .. }
+                | Status::TimedOut { .. }
+        )
     }
 }
 
+#[derive(Debug, Default)]
+pub struct StartupTiming {
+    // wall clock time until every node was up
+    pub total: Duration,
+    pub nodes: HashMap<NodeId, Duration>,
+}
+
 //Add all the assert implementations
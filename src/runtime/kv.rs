@@ -0,0 +1,415 @@
+use std::{collections::HashMap, fmt};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+
+use crate::{
+    packet::{NodeId, Packet, Payload, Rpc},
+    runtime::history::EventKind,
+    util::{ErrorLoggable, Rng},
+};
+
+pub const LIN_KV: &str = "lin-kv";
+pub const SEQ_KV: &str = "seq-kv";
+pub const LWW_KV: &str = "lww-kv";
+
+// Key-value stores hosted by the harness itself. Nodes talk to them with Rpcs to their
+// reserved node id, using maelstrom style bodies:
+//   {"type": "read", "msg_id": 1, "key": "x"}                 -> read_ok with "value"
+//   {"type": "write", "msg_id": 2, "key": "x", "value": 1}    -> write_ok
+//   {"type": "cas", "msg_id": 3, "key": "x", "from": 1, "to": 2,
+//    "create_if_not_exists": false}                           -> cas_ok
+// Replies carry "in_reply_to", failures are an "error" body with a maelstrom error code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KvService {
+    // every operation takes effect at once, in the order the service receives them
+    Linearizable,
+    // reads may be stale, but a client never sees a state older than one it has seen
+    Sequential,
+    // a handful of replicas that catch up with each other lazily, the newest write wins
+    LastWriteWins,
+}
+
+impl KvService {
+    pub fn node_id(&self) -> NodeId {
+        match self {
+            KvService::Linearizable => LIN_KV,
+            KvService::Sequential => SEQ_KV,
+            KvService::LastWriteWins => LWW_KV,
+        }
+        .to_string()
+    }
+
+    // Starts answering the packets sent to the returned channel on output_tx, the same
+    // way a container answers on its stdout.
+    pub fn start(self, output_tx: mpsc::Sender<EventKind>) -> mpsc::Sender<Packet> {
+        let (input_tx, input_rx) = mpsc::channel(50);
+        let node_id = self.node_id();
+        match self {
+            KvService::Linearizable => {
+                tokio::spawn(serve(node_id, LinKv::default(), input_rx, output_tx))
+            }
+            KvService::Sequential => {
+                tokio::spawn(serve(node_id, SeqKv::new(), input_rx, output_tx))
+            }
+            KvService::LastWriteWins => {
+                tokio::spawn(serve(node_id, LwwKv::new(), input_rx, output_tx))
+            }
+        };
+        input_tx
+    }
+}
+
+// the same seed every run, so a run that hit a stale read can be reproduced
+const SEED: u64 = 0x6b76;
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    Read {
+        key: Value,
+    },
+    Write {
+        key: Value,
+        value: Value,
+    },
+    Cas {
+        key: Value,
+        from: Value,
+        to: Value,
+        #[serde(default)]
+        create_if_not_exists: bool,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+enum KvError {
+    Malformed(String),
+    KeyDoesNotExist,
+    PreconditionFailed(String),
+}
+
+impl KvError {
+    fn code(&self) -> u32 {
+        match self {
+            KvError::Malformed(_) => 12,
+            KvError::KeyDoesNotExist => 20,
+            KvError::PreconditionFailed(_) => 22,
+        }
+    }
+}
+
+impl fmt::Display for KvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KvError::Malformed(e) => write!(f, "malformed request: {}", e),
+            KvError::KeyDoesNotExist => write!(f, "key does not exist"),
+            KvError::PreconditionFailed(e) => write!(f, "precondition failed: {}", e),
+        }
+    }
+}
+
+// What sets the services apart: which state a client's read or update gets to see.
+trait Store: Send + 'static {
+    fn read(&mut self, client: &str, key: &str) -> Option<Value>;
+    // Applies f to the current value of key and stores what it returns.
+    fn update<F>(&mut self, client: &str, key: &str, f: F) -> Result<(), KvError>
+    where
+        F: FnOnce(Option<&Value>) -> Result<Value, KvError>;
+}
+
+async fn serve<S: Store>(
+    node_id: NodeId,
+    mut store: S,
+    mut input_rx: mpsc::Receiver<Packet>,
+    output_tx: mpsc::Sender<EventKind>,
+) {
+    while let Some(packet) = input_rx.recv().await {
+        let Packet::Rpc(rpc) = packet else {
+            continue;
+        };
+        // never answer replies, or two services could keep erroring at each other
+        if rpc.data.field("in_reply_to").is_some() {
+            continue;
+        }
+        let reply = Rpc {
+            src: node_id.clone(),
+            dst: rpc.src.clone(),
+            data: Payload::Json(handle(&mut store, &rpc)),
+        };
+        output_tx
+            .send(EventKind::Packet(Packet::Rpc(reply)))
+            .await
+            .log_on_error();
+    }
+}
+
+fn handle<S: Store>(store: &mut S, rpc: &Rpc) -> Value {
+    let result = match rpc.data.body_as::<Request>() {
+        Ok(request) => apply(store, &rpc.src, request),
+        Err(e) => Err(KvError::Malformed(e.to_string())),
+    };
+    let mut body = match result {
+        Ok(body) => body,
+        Err(e) => json!({"type": "error", "code": e.code(), "text": e.to_string()}),
+    };
+    if let Some(msg_id) = rpc.data.field("msg_id") {
+        body["in_reply_to"] = msg_id;
+    }
+    body
+}
+
+fn apply<S: Store>(store: &mut S, client: &str, request: Request) -> Result<Value, KvError> {
+    match request {
+        Request::Read { key } => match store.read(client, &key.to_string()) {
+            Some(value) => Ok(json!({"type": "read_ok", "value": value})),
+            None => Err(KvError::KeyDoesNotExist),
+        },
+        Request::Write { key, value } => {
+            store.update(client, &key.to_string(), |_| Ok(value))?;
+            Ok(json!({"type": "write_ok"}))
+        }
+        Request::Cas {
+            key,
+            from,
+            to,
+            create_if_not_exists,
+        } => {
+            store.update(client, &key.to_string(), |current| match current {
+                Some(current) if *current == from => Ok(to),
+                Some(current) => Err(KvError::PreconditionFailed(format!(
+                    "expected {}, but had {}",
+                    from, current
+                ))),
+                None if create_if_not_exists => Ok(to),
+                None => Err(KvError::KeyDoesNotExist),
+            })?;
+            Ok(json!({"type": "cas_ok"}))
+        }
+    }
+}
+
+// Keys are kept as their JSON text, so "1" and 1 are different keys.
+type State = HashMap<String, Value>;
+
+#[derive(Default)]
+struct LinKv {
+    state: State,
+}
+
+impl Store for LinKv {
+    fn read(&mut self, _client: &str, key: &str) -> Option<Value> {
+        self.state.get(key).cloned()
+    }
+
+    fn update<F>(&mut self, _client: &str, key: &str, f: F) -> Result<(), KvError>
+    where
+        F: FnOnce(Option<&Value>) -> Result<Value, KvError>,
+    {
+        let value = f(self.state.get(key))?;
+        self.state.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+// Every update makes a new version of the store and applies to the latest one. Reads
+// pick any version between the last one the client saw and the latest, a client the
+// store hasn't seen yet starts at the oldest version any client has seen.
+struct SeqKv {
+    // per key, the versions it was written at with their values, oldest first. Only
+    // versions some client can still read are kept.
+    keys: HashMap<String, Vec<(u64, Value)>>,
+    latest: u64,
+    seen: HashMap<NodeId, u64>,
+    rng: Rng,
+}
+
+impl SeqKv {
+    fn new() -> Self {
+        SeqKv {
+            keys: HashMap::new(),
+            latest: 0,
+            seen: HashMap::new(),
+            rng: Rng::new(SEED),
+        }
+    }
+
+    // No client reads anything older than this version anymore.
+    fn oldest_seen(&self) -> u64 {
+        self.seen.values().min().copied().unwrap_or(self.latest)
+    }
+
+    fn value_at(&self, key: &str, version: u64) -> Option<Value> {
+        let versions = self.keys.get(key)?;
+        let (_, value) = versions.iter().rev().find(|(v, _)| *v <= version)?;
+        Some(value.clone())
+    }
+}
+
+impl Store for SeqKv {
+    fn read(&mut self, client: &str, key: &str) -> Option<Value> {
+        let oldest = match self.seen.get(client) {
+            Some(seen) => *seen,
+            None => self.oldest_seen(),
+        };
+        let version = oldest + self.rng.below(self.latest - oldest + 1);
+        self.seen.insert(client.to_string(), version);
+        self.value_at(key, version)
+    }
+
+    fn update<F>(&mut self, client: &str, key: &str, f: F) -> Result<(), KvError>
+    where
+        F: FnOnce(Option<&Value>) -> Result<Value, KvError>,
+    {
+        // even a failed cas has looked at the latest state
+        self.seen.insert(client.to_string(), self.latest);
+        let value = f(self.value_at(key, self.latest).as_ref())?;
+        self.latest += 1;
+        self.seen.insert(client.to_string(), self.latest);
+
+        let oldest = self.oldest_seen();
+        let versions = self.keys.entry(key.to_string()).or_default();
+        versions.push((self.latest, value));
+        // the last version at or before the oldest one seen hides everything before it
+        let hidden = versions
+            .iter()
+            .rposition(|(v, _)| *v <= oldest)
+            .unwrap_or(0);
+        versions.drain(..hidden);
+        Ok(())
+    }
+}
+
+const LWW_REPLICAS: usize = 3;
+
+// Every operation lands on a random replica, which first catches up with another random
+// replica half of the time. Values are stamped by arrival, so catching up keeps the newer one.
+struct LwwKv {
+    replicas: Vec<HashMap<String, (u64, Value)>>,
+    stamp: u64,
+    rng: Rng,
+}
+
+impl LwwKv {
+    fn new() -> Self {
+        LwwKv {
+            replicas: vec![HashMap::new(); LWW_REPLICAS],
+            stamp: 0,
+            rng: Rng::new(SEED),
+        }
+    }
+
+    fn pick_replica(&mut self) -> usize {
+        let replica = self.rng.below(LWW_REPLICAS as u64) as usize;
+        if self.rng.below(2) == 0 {
+            let other = self.rng.below(LWW_REPLICAS as u64) as usize;
+            for (key, (stamp, value)) in self.replicas[other].clone() {
+                let current = self.replicas[replica].get(&key);
+                if current.is_none_or(|(current, _)| *current < stamp) {
+                    self.replicas[replica].insert(key, (stamp, value));
+                }
+            }
+        }
+        replica
+    }
+}
+
+impl Store for LwwKv {
+    fn read(&mut self, _client: &str, key: &str) -> Option<Value> {
+        let replica = self.pick_replica();
+        self.replicas[replica]
+            .get(key)
+            .map(|(_, value)| value.clone())
+    }
+
+    fn update<F>(&mut self, _client: &str, key: &str, f: F) -> Result<(), KvError>
+    where
+        F: FnOnce(Option<&Value>) -> Result<Value, KvError>,
+    {
+        let replica = self.pick_replica();
+        let value = f(self.replicas[replica].get(key).map(|(_, value)| value))?;
+        self.stamp += 1;
+        self.replicas[replica].insert(key.to_string(), (self.stamp, value));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn kv_rpc(client: &str, body: Value) -> Rpc {
+    Rpc {
+        src: client.to_string(),
+        dst: LIN_KV.to_string(),
+        data: Payload::Json(body),
+    }
+}
+
+#[test]
+fn test_lin_kv_read_write_cas() {
+    let mut store = LinKv::default();
+    let mut call = |body: Value| handle(&mut store, &kv_rpc("n1", body));
+
+    let missing = call(json!({"type": "read", "msg_id": 1, "key": "x"}));
+    assert_eq!(missing["type"], "error");
+    assert_eq!(missing["code"], 20);
+    assert_eq!(missing["in_reply_to"], 1);
+
+    call(json!({"type": "write", "key": "x", "value": 1}));
+    let failed = call(json!({"type": "cas", "key": "x", "from": 2, "to": 3}));
+    assert_eq!(failed["code"], 22);
+    let cas = call(json!({"type": "cas", "key": "x", "from": 1, "to": 3}));
+    assert_eq!(cas["type"], "cas_ok");
+    let created =
+        call(json!({"type": "cas", "key": 1, "from": 0, "to": 5, "create_if_not_exists": true}));
+    assert_eq!(created["type"], "cas_ok");
+
+    let read = call(json!({"type": "read", "key": "x"}));
+    assert_eq!(read, json!({"type": "read_ok", "value": 3}));
+    assert_eq!(call(json!({"type": "frobnicate"}))["code"], 12);
+}
+
+#[test]
+fn test_seq_kv_reads_never_go_back_in_time() {
+    let mut store = SeqKv::new();
+    // the reader has seen the empty store, so every write is still readable for it
+    assert_eq!(store.read("reader", "\"x\""), None);
+    for value in 0..20 {
+        store
+            .update("writer", "\"x\"", |_| Ok(json!(value)))
+            .unwrap();
+    }
+
+    let mut last = -1;
+    for _ in 0..50 {
+        let value = store
+            .read("reader", "\"x\"")
+            .map_or(-1, |v| v.as_i64().unwrap());
+        assert!(value >= last);
+        last = value;
+    }
+    // its own write is the oldest state a client can see afterwards
+    store.update("reader", "\"x\"", |_| Ok(json!(100))).unwrap();
+    assert_eq!(store.read("reader", "\"x\""), Some(json!(100)));
+}
+
+#[test]
+fn test_seq_kv_forgets_versions_no_client_can_read() {
+    let mut store = SeqKv::new();
+    for value in 0..1000 {
+        store.update("c1", "x", |_| Ok(json!(value))).unwrap();
+        store.read("c2", "x");
+    }
+    // c2 falls behind by a few versions at most, nowhere near all 1000
+    assert!(store.keys["x"].len() < 50);
+    assert_eq!(store.read("c1", "x"), Some(json!(999)));
+}
+
+#[test]
+fn test_lww_kv_replicas_converge_on_newest_write() {
+    let mut store = LwwKv::new();
+    store.update("n1", "k", |_| Ok(json!("old"))).unwrap();
+    store.update("n2", "k", |_| Ok(json!("new"))).unwrap();
+
+    let reads: Vec<Option<Value>> = (0..50).map(|_| store.read("n1", "k")).collect();
+    assert_eq!(reads.last().unwrap(), &Some(json!("new")));
+}
@@ -1,20 +1,141 @@
 use anyhow::anyhow;
 
-use crate::packet::Packet;
+use crate::{packet::Packet, runtime::history::DecodeFailure};
+
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Framing {
+    // objects may span several lines, found by matching braces
+    Braces,
+    // exactly one JSON object per line
+    Ndjson,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecoderOptions {
+    pub framing: Framing,
+    // objects larger than this are reported as malformed and skipped
+    pub max_frame_size: usize,
+}
+
+impl Default for DecoderOptions {
+    fn default() -> Self {
+        DecoderOptions {
+            framing: Framing::Braces,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Decoded {
+    Packet(Packet),
+    // a line that is not part of any packet, i.e. the node logging
+    Text(String),
+    Malformed(DecodeFailure),
+}
 
 pub struct LineDecoder {
     buffer: Vec<u8>,
     brace_depth: i32,
     started: bool, // Track if we've seen an opening brace
+    options: DecoderOptions,
+    // after an oversized frame we drop lines until the next one starting with '{'
+    resyncing: bool,
 }
 
 impl LineDecoder {
     pub fn new() -> Self {
+        Self::with_options(DecoderOptions::default())
+    }
+
+    pub fn with_options(options: DecoderOptions) -> Self {
         LineDecoder {
             buffer: vec![],
             brace_depth: 0,
             started: false,
+            options,
+            resyncing: false,
+        }
+    }
+
+    // Feeds one line of output and returns whatever it completed.
+    pub fn decode_line(&mut self, line: String) -> Vec<Decoded> {
+        match self.options.framing {
+            Framing::Braces => self.decode_braced_line(line),
+            Framing::Ndjson => self.decode_ndjson_line(line).into_iter().collect(),
+        }
+    }
+
+    fn decode_ndjson_line(&mut self, line: String) -> Option<Decoded> {
+        if !line.trim_start().starts_with('{') {
+            return text(line);
+        }
+        if line.len() > self.options.max_frame_size {
+            return Some(self.oversized(line.into_bytes()));
         }
+        Some(match serde_json::from_str::<Packet>(&line) {
+            Ok(p) => Decoded::Packet(p),
+            Err(e) => malformed(line.into_bytes(), e.to_string()),
+        })
+    }
+
+    fn decode_braced_line(&mut self, line: String) -> Vec<Decoded> {
+        let mut decoded = vec![];
+        // A new object starts at column 0, so whatever we were collecting never got closed.
+        // Nested objects of pretty printed JSON are always indented.
+        let starts_object = line.starts_with('{');
+
+        if self.in_frame() && starts_object {
+            decoded.push(malformed(
+                std::mem::take(&mut self.buffer),
+                "unterminated object".to_string(),
+            ));
+            self.clear();
+        }
+
+        if self.resyncing {
+            if !starts_object {
+                return decoded;
+            }
+            self.resyncing = false;
+        }
+
+        if !self.in_frame() && !line.trim_start().starts_with('{') {
+            decoded.extend(text(line));
+            return decoded;
+        }
+
+        let result = self.add_to_buffer(line);
+        if self.brace_depth < 0 {
+            // more closing than opening braces, e.g. a stray '}' after an object
+            decoded.push(malformed(
+                std::mem::take(&mut self.buffer),
+                "unbalanced closing brace".to_string(),
+            ));
+            self.clear();
+        } else if let Some(result) = result {
+            decoded.push(match result {
+                Ok(p) => Decoded::Packet(p),
+                Err(e) => malformed(self.buffer.clone(), e.to_string()),
+            });
+            self.clear();
+        } else if self.buffer.len() > self.options.max_frame_size {
+            let raw = std::mem::take(&mut self.buffer);
+            decoded.push(self.oversized(raw));
+            self.clear();
+            self.resyncing = true;
+        }
+        decoded
+    }
+
+    fn oversized(&self, mut raw: Vec<u8>) -> Decoded {
+        raw.truncate(self.options.max_frame_size);
+        malformed(
+            raw,
+            format!("frame exceeds {} bytes", self.options.max_frame_size),
+        )
     }
 
     pub fn add_to_buffer(&mut self, line: String) -> Option<anyhow::Result<Packet>> {
@@ -45,13 +166,17 @@ impl LineDecoder {
         // When brace depth returns to 0 after starting, we have a complete JSON object
         if self.started && self.brace_depth == 0 {
             let result = serde_json::from_slice::<Packet>(&self.buffer);
-            eprintln!("current buffer: {}", String::from_utf8(self.buffer.clone()).unwrap());
             return Some(result.map_err(|e| anyhow!(e)));
         }
 
         None
     }
 
+    // Whether we are in the middle of a (possibly multi-line) JSON object.
+    pub fn in_frame(&self) -> bool {
+        self.started && self.brace_depth != 0
+    }
+
     pub fn clear(&mut self) {
         self.buffer.clear();
         self.brace_depth = 0;
@@ -59,12 +184,81 @@ impl LineDecoder {
     }
 }
 
+// Splits raw output into lines while it is read, never holding more than max bytes of
+// one line. The rest of a longer line is dropped up to the next newline.
+pub struct BoundedLines {
+    max: usize,
+    pending: Vec<u8>,
+    discarding: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Line {
+    Complete(Vec<u8>),
+    // the first max bytes of a line that was longer
+    Oversized(Vec<u8>),
+}
+
+impl BoundedLines {
+    pub fn new(max: usize) -> Self {
+        BoundedLines {
+            max,
+            pending: vec![],
+            discarding: false,
+        }
+    }
+
+    pub fn feed(&mut self, mut bytes: &[u8]) -> Vec<Line> {
+        let mut lines = vec![];
+        while !bytes.is_empty() {
+            let (chunk, ended) = match bytes.iter().position(|b| *b == b'\n') {
+                Some(end) => (&bytes[..end], true),
+                None => (bytes, false),
+            };
+            bytes = &bytes[(chunk.len() + ended as usize)..];
+            if self.discarding {
+                self.discarding = !ended;
+                continue;
+            }
+            let room = self.max - self.pending.len();
+            if chunk.len() > room {
+                self.pending.extend_from_slice(&chunk[..room]);
+                lines.push(Line::Oversized(std::mem::take(&mut self.pending)));
+                self.discarding = !ended;
+                continue;
+            }
+            self.pending.extend_from_slice(chunk);
+            if ended {
+                lines.push(Line::Complete(std::mem::take(&mut self.pending)));
+            }
+        }
+        lines
+    }
+
+    // What's left of a last line that never got its newline, e.g. at the end of output.
+    pub fn rest(&mut self) -> Option<Vec<u8>> {
+        (!self.pending.is_empty()).then(|| std::mem::take(&mut self.pending))
+    }
+}
+
+fn text(line: String) -> Option<Decoded> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    Some(Decoded::Text(line))
+}
+
+fn malformed(raw: Vec<u8>, error: String) -> Decoded {
+    Decoded::Malformed(DecodeFailure { raw, error })
+}
+
 #[test]
 fn test_clear() {
     let mut d = LineDecoder {
         buffer: vec![1, 2, 3],
         brace_depth: 2,
         started: true,
+        ..LineDecoder::new()
     };
     d.clear();
     assert!(d.buffer.is_empty());
@@ -134,3 +328,143 @@ fn test_brace_depth_tracking() {
     d.add_to_buffer("  }".to_string());
     assert_eq!(d.brace_depth, 1);
 }
+
+#[cfg(test)]
+const RPC_LINE: &str =
+    "{\"type\": \"rpc\", \"src\": \"hi\", \"dst\": \"todo!()\", \"data\": \"YWRzZmFz\"}";
+
+#[cfg(test)]
+fn is_packet(d: &Decoded) -> bool {
+    matches!(d, Decoded::Packet(_))
+}
+
+#[test]
+fn test_logs_interleaved_with_packets() {
+    let mut d = LineDecoder::new();
+    assert_eq!(
+        d.decode_line("starting up".to_string()),
+        vec![Decoded::Text("starting up".to_string())]
+    );
+    assert!(is_packet(&d.decode_line(RPC_LINE.to_string())[0]));
+    assert_eq!(
+        d.decode_line("sent rpc".to_string()),
+        vec![Decoded::Text("sent rpc".to_string())]
+    );
+    assert!(d.decode_line(String::new()).is_empty());
+}
+
+#[test]
+fn test_partial_object_is_completed_by_later_lines() {
+    let mut d = LineDecoder::new();
+    assert!(d.decode_line("{".to_string()).is_empty());
+    assert!(
+        d.decode_line("  \"type\": \"rpc\", \"src\": \"hi\",".to_string())
+            .is_empty()
+    );
+    assert!(
+        d.decode_line("  \"dst\": \"a\", \"data\": \"}\"".to_string())
+            .is_empty()
+    );
+    let decoded = d.decode_line("}".to_string());
+    assert_eq!(decoded.len(), 1);
+    assert!(is_packet(&decoded[0]));
+}
+
+#[test]
+fn test_stray_closing_brace_is_a_log_line() {
+    let mut d = LineDecoder::new();
+    assert_eq!(
+        d.decode_line("} done".to_string()),
+        vec![Decoded::Text("} done".to_string())]
+    );
+    assert!(is_packet(&d.decode_line(RPC_LINE.to_string())[0]));
+}
+
+#[test]
+fn test_unbalanced_closing_brace_does_not_get_stuck() {
+    let mut d = LineDecoder::new();
+    let decoded = d.decode_line(format!("{}}}", RPC_LINE));
+    assert!(matches!(decoded[..], [Decoded::Malformed(_)]));
+    assert!(!d.in_frame());
+    assert!(is_packet(&d.decode_line(RPC_LINE.to_string())[0]));
+}
+
+#[test]
+fn test_unterminated_object_resyncs_on_next_object() {
+    let mut d = LineDecoder::new();
+    assert!(d.decode_line("{\"type\": \"rpc\",".to_string()).is_empty());
+    assert!(d.decode_line("  \"src\": \"hi\"".to_string()).is_empty());
+    let decoded = d.decode_line(RPC_LINE.to_string());
+    assert_eq!(decoded.len(), 2);
+    match &decoded[0] {
+        Decoded::Malformed(failure) => {
+            assert_eq!(
+                failure.raw,
+                b"{\"type\": \"rpc\",  \"src\": \"hi\"".to_vec()
+            );
+            assert_eq!(failure.error, "unterminated object");
+        }
+        other => panic!("expected malformed, got {:?}", other),
+    }
+    assert!(is_packet(&decoded[1]));
+}
+
+#[test]
+fn test_oversized_frame_is_dropped_until_next_object() {
+    let mut d = LineDecoder::with_options(DecoderOptions {
+        max_frame_size: 16,
+        ..Default::default()
+    });
+    assert!(d.decode_line("{".to_string()).is_empty());
+    let decoded = d.decode_line("  \"data\": \"this is far too long\",".to_string());
+    match &decoded[..] {
+        [Decoded::Malformed(failure)] => assert_eq!(failure.raw.len(), 16),
+        other => panic!("expected malformed, got {:?}", other),
+    }
+    // the rest of the oversized object is skipped rather than logged
+    assert!(d.decode_line("  \"src\": \"hi\"".to_string()).is_empty());
+    assert!(d.decode_line("}".to_string()).is_empty());
+    assert!(matches!(
+        d.decode_line("{\"a\": 1}".to_string())[..],
+        [Decoded::Malformed(_)]
+    ));
+}
+
+#[test]
+fn test_ndjson_decodes_one_object_per_line() {
+    let mut d = LineDecoder::with_options(DecoderOptions {
+        framing: Framing::Ndjson,
+        ..Default::default()
+    });
+    assert!(is_packet(&d.decode_line(RPC_LINE.to_string())[0]));
+    // an object split over lines is two malformed frames, not one packet
+    assert!(matches!(
+        d.decode_line("{\"type\": \"rpc\",".to_string())[..],
+        [Decoded::Malformed(_)]
+    ));
+    assert_eq!(
+        d.decode_line("  \"src\": \"hi\"}".to_string()),
+        vec![Decoded::Text("  \"src\": \"hi\"}".to_string())]
+    );
+    assert!(is_packet(&d.decode_line(RPC_LINE.to_string())[0]));
+}
+
+#[test]
+fn test_bounded_lines_drop_the_rest_of_long_lines_while_reading() {
+    let mut lines = BoundedLines::new(8);
+    // a node that doesn't end its line doesn't make the buffer grow
+    assert_eq!(lines.feed(b"0123456"), vec![]);
+    assert_eq!(
+        lines.feed(b"789abcdef"),
+        vec![Line::Oversized(b"01234567".to_vec())]
+    );
+    for _ in 0..100 {
+        assert_eq!(lines.feed(b"more and more"), vec![]);
+        assert!(lines.pending.is_empty());
+    }
+    assert_eq!(
+        lines.feed(b" end\nok\nnext"),
+        vec![Line::Complete(b"ok".to_vec())]
+    );
+    assert_eq!(lines.rest(), Some(b"next".to_vec()));
+}
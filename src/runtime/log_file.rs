@@ -0,0 +1,57 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{
+    packet::NodeId,
+    runtime::history::{Event, Log},
+};
+
+// Per-node log files, opened lazily the first time a node logs something.
+pub struct LogFiles {
+    dir: PathBuf,
+    files: HashMap<NodeId, fs::File>,
+}
+
+impl LogFiles {
+    pub fn new(dir: PathBuf) -> Self {
+        LogFiles {
+            dir,
+            files: HashMap::new(),
+        }
+    }
+
+    pub async fn write(&mut self, event: &Event, log: &Log) -> anyhow::Result<()> {
+        if !self.files.contains_key(&event.node) {
+            fs::create_dir_all(&self.dir).await?;
+            let file = fs::File::create(self.dir.join(file_name(&event.node))).await?;
+            self.files.insert(event.node.clone(), file);
+        }
+        let file = self.files.get_mut(&event.node).unwrap();
+        let line = format!("[+{:.3}s] {}\n", event.at.as_secs_f64(), log);
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+// <node>.log, with everything but letters, digits, - and _ escaped as %XX so that a node
+// name like "../n1" or "a/b" stays inside the log directory.
+fn file_name(node: &str) -> String {
+    let mut name = String::new();
+    for byte in node.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => name.push(byte as char),
+            _ => name.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    name + ".log"
+}
+
+#[test]
+fn test_log_file_names_stay_in_the_log_dir() {
+    assert_eq!(file_name("node-1"), "node-1.log");
+    assert_eq!(file_name("../etc/passwd"), "%2E%2E%2Fetc%2Fpasswd.log");
+    assert_eq!(file_name(".."), "%2E%2E.log");
+    assert_ne!(file_name("a/b"), file_name("a_b"));
+}
@@ -1,10 +1,21 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    pin::{Pin, pin},
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
 
 use anyhow::anyhow;
-use futures::TryFutureExt;
+use futures::{
+    Stream, StreamExt,
+    future::{self, Either, join_all},
+    stream,
+};
 use tokio::{
     sync::{mpsc, oneshot},
-    time::timeout,
+    time::{self, timeout_at},
 };
 
 /*
@@ -15,24 +26,43 @@ use tokio::{
  * 4. assert with the checkers
  */
 use crate::{
+    checker::Invariant,
     packet::{Init, NodeId, Packet},
     runtime::{
-        container::RunnableContainer,
-        input::{Env, History, Test},
+        clock::{Clock, handle_clock_packet},
+        container::{NodeSpec, RunnableContainer},
+        end::{EndCondition, EndWatch},
+        history::{Event, EventKind, History, Violation},
+        input::{Report, StartupTiming, Status, Test},
+        log_file::LogFiles,
+        routing::{DefaultRouter, LinkFaults, Routing},
     },
-    util::ErrorLoggable,
+    util::{ErrorLoggable, new_run_id},
 };
 
+pub use container::{LeftoverContainer, clean_leftover_containers};
+
+pub mod adversary;
+pub mod clock;
+pub mod codec;
 mod container;
+pub mod end;
+pub mod history;
 pub mod input;
+pub mod kv;
 pub mod line_decoder;
+mod log_file;
+pub mod routing;
 #[cfg(test)]
 mod test;
+pub mod workload;
 
 pub type BivRuntime = Runtime<container::Container>;
 
 pub struct Runtime<C: RunnableContainer> {
     pub(crate) containers: HashMap<NodeId, C>,
+    // scopes container names and labels to this runtime
+    run_id: String,
 }
 
 //Launch nodes in test. But how can I get binary image?
@@ -40,73 +70,120 @@ impl<C: RunnableContainer> Runtime<C> {
     pub fn new() -> Self {
         Self {
             containers: HashMap::new(),
+            run_id: new_run_id(),
+        }
+    }
+
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    // Maps logical node ids to the docker containers running them.
+    pub fn container_names(&self) -> HashMap<NodeId, String> {
+        self.containers
+            .iter()
+            .map(|(node_name, c)| (node_name.clone(), c.container_name()))
+            .collect()
+    }
+
+    pub async fn launch_test(&mut self, t: Test) -> anyhow::Result<Report> {
+        let report = self.start_test(t).await?.finish().await?;
+        if report.status.is_aborted() {
+            self.stop_nodes().await;
         }
+        Ok(report)
     }
 
-    pub async fn launch_test(&mut self, t: Test) -> anyhow::Result<History> {
+    // Like launch_test, but returns as soon as the test is running so that its events
+    // can be followed while they happen. The nodes of an aborted run are left to
+    // stop_nodes.
+    pub async fn start_test(&mut self, mut t: Test) -> anyhow::Result<TestRun> {
         let (tx, rx) = oneshot::channel();
 
-        self.launch_all_nodes(&t).await?;
+        let startup = self.launch_all_nodes(&t).await?;
 
         // consume at interconnect nodes but at the same time, gatehr hisory
         //connect all outputs to history gather
-        self.interconnect_nodes(tx, Duration::from_secs(t.end_delay_secs))
+        // the invariants and end conditions keep their state for this run only
+        let end_conditions = std::mem::take(&mut t.end_conditions);
+        let invariants = std::mem::take(&mut t.invariants);
+        let events = self
+            .interconnect_nodes(tx, &t, end_conditions, invariants)
             .await?;
 
-        //send init packets
-        for (node_name, input_packets) in t.input {
-            if let Some(container) = self.containers.get(&node_name) {
-                let input_tx = container.stdin_tx().clone();
-                for packet in input_packets {
-                    let input_tx = input_tx.clone();
+        Ok(TestRun {
+            events,
+            history: rx,
+            t,
+            run_id: self.run_id.clone(),
+            startup,
+        })
+    }
 
-                    //TODO: fix how to pass init packets
-                    let node_name = node_name.clone();
-                    tokio::spawn(async move {
-                        input_tx
-                            .send(Packet::Init(Init {
-                                node_id: node_name,
-                                data: packet,
-                            }))
-                            .await
-                            .unwrap()
-                    });
+    // Launches nodes concurrently, at most `launch_concurrency` at a time.
+    // If any of them fails to start, the ones that did start are stopped again
+    // so that we never leave half of a cluster running.
+    async fn launch_all_nodes(&mut self, t: &Test) -> anyhow::Result<StartupTiming> {
+        let started_at = Instant::now();
+        let concurrency = t.launch_concurrency.unwrap_or(t.nodes.len()).max(1);
+
+        let results: Vec<(NodeId, anyhow::Result<C>, Duration)> = stream::iter(t.nodes.clone())
+            .map(|node_name| {
+                let spec = NodeSpec {
+                    image_name: t.image_name,
+                    image_tag: t.image_tag,
+                    env: t.env_of(&node_name),
+                    node_name: node_name.clone(),
+                    run_id: self.run_id.clone(),
+                    codec: t.codec_of(&node_name),
+                };
+                async move {
+                    let launched_at = Instant::now();
+                    let result = C::launch(spec).await;
+                    (node_name, result, launched_at.elapsed())
                 }
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+
+        let mut startup = StartupTiming::default();
+        let mut errors = vec![];
+        for (node_name, result, elapsed) in results {
+            match result {
+                Ok(c) => {
+                    self.containers.insert(node_name.clone(), c);
+                    startup.nodes.insert(node_name, elapsed);
+                }
+                Err(e) => errors.push(format!("{}: {}", node_name, e)),
             }
         }
 
-        rx.await.map_err(|e| anyhow!(e))
-    }
-
-    async fn launch_all_nodes(&mut self, t: &Test) -> anyhow::Result<()> {
-        for node_name in &t.nodes {
-            self.launch_node(t.image_name, t.image_tag, t.env.clone(), node_name.clone())
-                .await?;
+        if !errors.is_empty() {
+            self.stop_nodes().await;
+            return Err(anyhow!("Failed to launch nodes: {}", errors.join(", ")));
         }
-        Ok(())
+
+        startup.total = started_at.elapsed();
+        Ok(startup)
     }
 
-    async fn launch_node(
-        &mut self,
-        image_name: &'static str,
-        image_tag: &'static str,
-        env: Vec<Env>,
-        node_name: NodeId,
-    ) -> anyhow::Result<()> {
-        let c = C::launch(image_name, image_tag, env, node_name.clone())
-            .map_err(|e| anyhow!(e))
-            .await?;
-        self.containers.insert(node_name, c);
-        Ok(())
+    // Stops and forgets every container of the runtime.
+    pub async fn stop_nodes(&mut self) {
+        join_all(self.containers.values().map(|c| c.stop())).await;
+        self.containers.clear();
     }
 
     async fn interconnect_nodes(
         &self,
         tx: oneshot::Sender<History>,
-        timeout_duration: Duration,
-    ) -> anyhow::Result<()> {
+        t: &Test,
+        mut end_conditions: Vec<EndCondition>,
+        invariants: Vec<Box<dyn Invariant>>,
+    ) -> anyhow::Result<mpsc::Receiver<Event>> {
+        let clock = Clock::start();
         let mut stdin_txs: HashMap<String, mpsc::Sender<Packet>> = HashMap::new();
-        let mut stdouts: HashMap<String, mpsc::Receiver<Packet>> = HashMap::new();
+        let mut stdouts: HashMap<String, mpsc::Receiver<EventKind>> = HashMap::new();
 
         for (container_name, container) in &self.containers {
             let (tx, rx) = mpsc::channel(50);
@@ -116,67 +193,277 @@ impl<C: RunnableContainer> Runtime<C> {
             stdouts.insert(container_name.clone(), rx);
         }
 
+        for service in &t.services {
+            let node_id = service.node_id();
+            if stdin_txs.contains_key(&node_id) {
+                return Err(anyhow!("{} is reserved for a service", node_id));
+            }
+            let (tx, rx) = mpsc::channel(50);
+            stdin_txs.insert(node_id.clone(), service.start(tx));
+            stdouts.insert(node_id, rx);
+        }
+
+        //send init packets, before any workload client can talk to the nodes
+        for (node_name, input_packets) in &t.input {
+            if let Some(container) = self.containers.get(node_name) {
+                let input_tx = container.stdin_tx();
+                for packet in input_packets.clone() {
+                    //TODO: fix how to pass init packets
+                    let init = Packet::Init(Init {
+                        node_id: node_name.clone(),
+                        data: packet,
+                    });
+                    input_tx.send(init).await.log_on_error();
+                }
+            }
+        }
+
+        let mut first_client = 1;
+        for workload in &t.workloads {
+            for client in workload.start(first_client, &t.nodes) {
+                if stdin_txs.contains_key(&client.id) {
+                    return Err(anyhow!("{} is reserved for a workload client", client.id));
+                }
+                stdin_txs.insert(client.id.clone(), client.input_tx);
+                stdouts.insert(client.id, client.output_rx);
+                first_client += 1;
+            }
+        }
+
         let (history_packet_tx, history_packet_rx) = mpsc::channel(100);
 
-        for (_, output_rx) in stdouts {
+        let mut middleware = t.middleware.clone();
+        middleware.push(Arc::new(LinkFaults(t.faults.clone())));
+        let routing = Routing {
+            router: t.router.clone().unwrap_or_else(|| Arc::new(DefaultRouter)),
+            middleware,
+        };
+        // services and workload clients only get the rpcs sent to them
+        let mut nodes: Vec<NodeId> = self.containers.keys().cloned().collect();
+        nodes.sort();
+
+        for (node_name, output_rx) in stdouts {
             //launch a task per container
             let inputs = stdin_txs.clone();
+            let routing = routing.clone();
+            let nodes = nodes.clone();
+            let skew = t.clock_skew.get(&node_name).copied().unwrap_or_default();
             let history_packet_tx = history_packet_tx.clone();
 
             tokio::spawn(async move {
                 let mut output_rx = output_rx;
-                while let Some(packet) = output_rx.recv().await {
-                    history_packet_tx.send(packet.clone()).await.log_on_error();
-                    match packet {
-                        Packet::Rpc(_) => {
-                            if let Some(input_tx) =
-                                inputs.get(&packet.dst().unwrap_or(String::new()))
-                            {
-                                let input_tx = input_tx.clone();
-                                let packet = packet.clone();
-                                tokio::spawn(async move { input_tx.send(packet).await.unwrap() });
-                            }
-                        }
-                        Packet::Broadcast(_) => {
-                            for (node_id, input_tx) in &inputs {
-                                if node_id != &packet.src().unwrap_or(String::new()) {
-                                    let input_tx = input_tx.clone();
-                                    let packet = packet.clone();
-                                    tokio::spawn(
-                                        async move { input_tx.send(packet).await.unwrap() },
-                                    );
-                                }
-                            }
-                        }
-                        _ => {} //Init
+                // once the run is over the node's channels are dropped, which stops the
+                // services and workload clients
+                while let Either::Left((Some(kind), _)) =
+                    future::select(pin!(output_rx.recv()), pin!(history_packet_tx.closed())).await
+                {
+                    let event = Event {
+                        at: clock.elapsed(),
+                        node: node_name.clone(),
+                        kind,
+                    };
+                    // the run is over once the history stops taking events
+                    if history_packet_tx.send(event.clone()).await.is_err() {
+                        break;
+                    }
+                    let EventKind::Packet(packet) = event.kind else {
+                        continue;
+                    };
+                    if let Some(input_tx) = inputs.get(&node_name) {
+                        handle_clock_packet(&packet, &node_name, input_tx, clock, skew);
+                    }
+
+                    for delivery in routing.route(&node_name, &packet, &nodes) {
+                        // a middleware may have redirected it to a node that isn't there
+                        let Some(input_tx) = inputs.get(&delivery.dst).cloned() else {
+                            continue;
+                        };
+                        tokio::spawn(async move {
+                            time::sleep(delivery.delay).await;
+                            input_tx.send(delivery.packet).await.log_on_error();
+                        });
                     }
                 }
             });
         }
 
-        tokio::spawn(gather_node_outputs(history_packet_rx, tx, timeout_duration));
+        if end_conditions.is_empty() {
+            let idle = Duration::from_secs(t.end_delay_secs);
+            end_conditions.push(EndCondition::quiescent(idle));
+        }
+        let (live_tx, live_rx) = mpsc::channel(LIVE_EVENTS);
+        let gathered = Gathered {
+            history: History::default(),
+            live_tx,
+            result_tx: tx,
+        };
+        tokio::spawn(gather_node_outputs(
+            history_packet_rx,
+            gathered,
+            EndWatch::new(end_conditions, t.max_duration),
+            t.log_dir.clone(),
+            t.strict_decoding,
+            invariants,
+            clock,
+        ));
 
-        Ok(())
+        Ok(live_rx)
     }
 }
 
-async fn gather_node_outputs(
-    mut history_packet_rx: mpsc::Receiver<Packet>,
+// Events a TestRun holds for whoever follows it live.
+const LIVE_EVENTS: usize = 1024;
+
+// The history of a run while it is gathered, and where its events go.
+struct Gathered {
+    history: History,
+    live_tx: mpsc::Sender<Event>,
     result_tx: oneshot::Sender<History>,
-    timeout_duration: Duration,
+}
+
+impl Gathered {
+    fn record(&mut self, event: Event) {
+        // a follower that falls behind misses events rather than holding them all twice,
+        // and nobody has to follow the run live
+        if !self.live_tx.is_closed() {
+            self.live_tx.try_send(event.clone()).ok();
+        }
+        self.history.0.push(event);
+    }
+}
+
+async fn gather_node_outputs(
+    mut history_packet_rx: mpsc::Receiver<Event>,
+    mut gathered: Gathered,
+    mut end: EndWatch,
+    log_dir: Option<PathBuf>,
+    strict_decoding: bool,
+    mut invariants: Vec<Box<dyn Invariant>>,
+    clock: Clock,
 ) {
-    let mut history = vec![];
+    let mut log_files = log_dir.map(LogFiles::new);
     //gather all outputs to history
     loop {
-        let packet = timeout(timeout_duration, history_packet_rx.recv()).await;
-        match packet {
-            Ok(Some(packet)) => {
-                history.push(packet);
+        // checked up front too, nodes that never stop talking never let the timeout fire
+        if let Some(limit) = end.timed_out() {
+            let event = Event {
+                at: clock.elapsed(),
+                node: HARNESS.to_string(),
+                kind: EventKind::TimedOut(limit),
+            };
+            gathered.record(event);
+            break;
+        }
+        let event = match end.deadline() {
+            Some(deadline) => timeout_at(deadline, history_packet_rx.recv()).await,
+            None => Ok(history_packet_rx.recv().await),
+        };
+        match event {
+            Ok(Some(event)) => {
+                if let (Some(log_files), EventKind::Log(log)) = (&mut log_files, &event.kind) {
+                    log_files.write(&event, log).await.log_on_error();
+                }
+                let decode_failed = matches!(event.kind, EventKind::DecodeFailure(_));
+                let violation = invariants.iter_mut().find_map(|i| {
+                    let error = i.observe(&event).err()?;
+                    Some(Violation {
+                        invariant: i.name(),
+                        error,
+                    })
+                });
+                let (at, node) = (event.at, event.node.clone());
+                gathered.record(event);
+                if let Some(violation) = violation {
+                    let event = Event {
+                        at,
+                        node,
+                        kind: EventKind::Violation(violation),
+                    };
+                    gathered.record(event);
+                    break;
+                }
+                if strict_decoding && decode_failed {
+                    break;
+                }
+                let history = &gathered.history;
+                if end.observe(history.0.last().unwrap(), history) {
+                    break;
+                }
             }
+            Err(_) if end.timed_out().is_some() => continue,
             _ => {
                 break;
             }
         }
     }
-    result_tx.send(History(history)).log_on_error();
+    gathered.result_tx.send(gathered.history).log_on_error();
+}
+
+// A running test. As a Stream it yields the events of the run while they happen, it ends
+// with the run. It holds up to LIVE_EVENTS events that weren't taken yet, the ones that
+// come while it is full are left out of it. Every event still ends up in the report.
+pub struct TestRun {
+    events: mpsc::Receiver<Event>,
+    history: oneshot::Receiver<History>,
+    t: Test,
+    run_id: String,
+    startup: StartupTiming,
+}
+
+impl TestRun {
+    // Waits for the run to end.
+    pub async fn finish(self) -> anyhow::Result<Report> {
+        // the events nobody took are in the history anyway
+        drop(self.events);
+        let history = self.history.await.map_err(|e| anyhow!(e))?;
+        Ok(Report {
+            run_id: self.run_id,
+            status: final_status(&self.t, &history),
+            history,
+            startup: self.startup,
+        })
+    }
+}
+
+impl Stream for TestRun {
+    type Item = Event;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.get_mut().events.poll_recv(cx)
+    }
+}
+
+// Node of the events the harness itself records.
+const HARNESS: &str = "biv";
+
+// Events kept before a failed invariant when the test doesn't say.
+const SNAPSHOT_EVENTS: usize = 20;
+
+fn final_status(t: &Test, history: &History) -> Status {
+    if let Some(last) = history.0.last()
+        && let EventKind::Violation(violation) = &last.kind
+    {
+        let events = &history.0[..history.0.len() - 1];
+        let keep = t.snapshot_events.unwrap_or(SNAPSHOT_EVENTS);
+        return Status::InvariantViolated {
+            invariant: violation.invariant.clone(),
+            node: last.node.clone(),
+            error: violation.error.clone(),
+            snapshot: events[events.len().saturating_sub(keep)..].to_vec(),
+        };
+    }
+    if let Some(last) = history.0.last()
+        && let EventKind::TimedOut(after) = last.kind
+    {
+        return Status::TimedOut { after };
+    }
+    if t.strict_decoding
+        && let Some((event, failure)) = history.decode_failures().next()
+    {
+        return Status::DecodeFailed {
+            node: event.node.clone(),
+            error: failure.error.clone(),
+        };
+    }
+    Status::Completed
 }
@@ -0,0 +1,205 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::packet::{NodeId, Packet};
+
+// A packet on its way to one node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delivery {
+    // the node that sent it, whatever src the packet claims
+    pub from: NodeId,
+    pub dst: NodeId,
+    pub packet: Packet,
+    // how long the packet is held before the node gets it
+    pub delay: Duration,
+}
+
+// Decides which of the nodes a packet a node sent goes to. DefaultRouter delivers rpcs to
+// their dst, broadcasts to every other node and multicasts to their dsts.
+// The nodes are the test's nodes only. Services and workload clients aren't among them,
+// they only get the rpcs addressed to them.
+pub trait Router: Send + Sync {
+    fn route(&self, from: &NodeId, packet: &Packet, nodes: &[NodeId]) -> Vec<Delivery>;
+}
+
+// Runs on every delivery the router came up with, and may inspect, change, drop, delay,
+// duplicate or redirect it. A middleware returns the deliveries the next one gets.
+pub trait Middleware: Send + Sync {
+    fn handle(&self, delivery: Delivery) -> Vec<Delivery>;
+}
+
+pub struct DefaultRouter;
+
+impl Router for DefaultRouter {
+    fn route(&self, from: &NodeId, packet: &Packet, nodes: &[NodeId]) -> Vec<Delivery> {
+        let dsts = match packet {
+            Packet::Rpc(rpc) => vec![rpc.dst.clone()],
+            _ => destinations(packet, nodes.iter()),
+        };
+        dsts.into_iter()
+            .map(|dst| Delivery {
+                from: from.clone(),
+                dst,
+                packet: packet.clone(),
+                delay: Duration::ZERO,
+            })
+            .collect()
+    }
+}
+
+// The test's link faults, as the last middleware.
+pub struct LinkFaults(pub Vec<LinkFault>);
+
+impl Middleware for LinkFaults {
+    fn handle(&self, mut delivery: Delivery) -> Vec<Delivery> {
+        let Some(delay) = link_delay(&self.0, &delivery.from, &delivery.dst) else {
+            return vec![];
+        };
+        delivery.delay += delay;
+        vec![delivery]
+    }
+}
+
+// A router and the middleware its deliveries go through, in order.
+#[derive(Clone)]
+pub struct Routing {
+    pub router: Arc<dyn Router>,
+    pub middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Routing {
+    pub fn route(&self, from: &NodeId, packet: &Packet, nodes: &[NodeId]) -> Vec<Delivery> {
+        let mut deliveries = self.router.route(from, packet, nodes);
+        for middleware in &self.middleware {
+            deliveries = deliveries
+                .into_iter()
+                .flat_map(|delivery| middleware.handle(delivery))
+                .collect();
+        }
+        deliveries
+    }
+}
+
+// Messes with packets on the link from src to dst. A None end matches any node,
+// so faults can target a single link, everything a node sends, or everything it receives.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkFault {
+    pub src: Option<NodeId>,
+    pub dst: Option<NodeId>,
+    pub kind: FaultKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    Drop,
+    Delay(Duration),
+}
+
+impl LinkFault {
+    pub fn matches(&self, src: &str, dst: &str) -> bool {
+        self.src.as_deref().is_none_or(|s| s == src) && self.dst.as_deref().is_none_or(|d| d == dst)
+    }
+}
+
+// Nodes a packet has to be delivered to.
+pub fn destinations<'a>(packet: &Packet, nodes: impl Iterator<Item = &'a NodeId>) -> Vec<NodeId> {
+    let mut nodes: Vec<NodeId> = match packet {
+        Packet::Rpc(rpc) => nodes.filter(|n| **n == rpc.dst).cloned().collect(),
+        Packet::Broadcast(broadcast) => nodes.filter(|n| **n != broadcast.src).cloned().collect(),
+        Packet::Multicast(multicast) => nodes
+            .filter(|n| multicast.dsts.contains(n))
+            .cloned()
+            .collect(),
+        // handled by the harness itself
+        Packet::Init(_) | Packet::Timer(_) | Packet::Tick(_) | Packet::Time(_) => vec![],
+    };
+    nodes.sort();
+    nodes
+}
+
+// How long to hold a packet on the link from src to dst, or None if it's dropped.
+// Delays of every matching fault add up.
+pub fn link_delay(faults: &[LinkFault], src: &str, dst: &str) -> Option<Duration> {
+    let mut delay = Duration::ZERO;
+    for fault in faults.iter().filter(|f| f.matches(src, dst)) {
+        match fault.kind {
+            FaultKind::Drop => return None,
+            FaultKind::Delay(d) => delay += d,
+        }
+    }
+    Some(delay)
+}
+
+#[cfg(test)]
+fn routed(middleware: Vec<Arc<dyn Middleware>>, packet: Packet) -> Vec<Delivery> {
+    let routing = Routing {
+        router: Arc::new(DefaultRouter),
+        middleware,
+    };
+    let nodes = ["n1", "n2", "n3"].map(String::from);
+    let from = packet.src().unwrap();
+    routing.route(&from, &packet, &nodes)
+}
+
+#[test]
+fn test_middleware_drops_deliveries() {
+    use crate::packet::{Broadcast, Rpc};
+
+    // cuts n3 off from everything
+    struct Isolate;
+    impl Middleware for Isolate {
+        fn handle(&self, delivery: Delivery) -> Vec<Delivery> {
+            match delivery.dst.as_str() {
+                "n3" => vec![],
+                _ => vec![delivery],
+            }
+        }
+    }
+
+    let broadcast = Packet::Broadcast(Broadcast {
+        src: "n1".to_string(),
+        data: "hi".into(),
+    });
+    let dsts =
+        |deliveries: Vec<Delivery>| deliveries.into_iter().map(|d| d.dst).collect::<Vec<_>>();
+    assert_eq!(dsts(routed(vec![], broadcast.clone())), ["n2", "n3"]);
+    assert_eq!(dsts(routed(vec![Arc::new(Isolate)], broadcast)), ["n2"]);
+
+    // workload clients aren't nodes, but still get their replies
+    let reply = Packet::Rpc(Rpc {
+        src: "n1".to_string(),
+        dst: "c1".to_string(),
+        data: "ok".into(),
+    });
+    assert_eq!(dsts(routed(vec![Arc::new(Isolate)], reply)), ["c1"]);
+}
+
+#[test]
+fn test_middleware_modifies_deliveries() {
+    use crate::packet::{Payload, Rpc};
+
+    // what n2 gets is shouted at it
+    struct Shout;
+    impl Middleware for Shout {
+        fn handle(&self, mut delivery: Delivery) -> Vec<Delivery> {
+            if let Packet::Rpc(rpc) = &mut delivery.packet
+                && let Payload::Text(text) = &rpc.data
+            {
+                rpc.data = Payload::Text(text.to_uppercase());
+            }
+            vec![delivery]
+        }
+    }
+
+    let rpc = Packet::Rpc(Rpc {
+        src: "n1".to_string(),
+        dst: "n2".to_string(),
+        data: "hi".into(),
+    });
+    let deliveries = routed(vec![Arc::new(Shout)], rpc);
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].dst, "n2");
+    assert_eq!(
+        deliveries[0].packet.data(),
+        &Payload::Text("HI".to_string())
+    );
+}
@@ -1,10 +1,29 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+use futures::StreamExt;
+use serde_json::json;
 use tokio::sync::{mpsc, oneshot};
 
 use crate::{
-    packet::{Broadcast, Packet, Rpc},
-    runtime::{Runtime, container::MockContainer, input::Test},
+    checker::{Checker, NoLogContaining, invariant},
+    packet::{Broadcast, Multicast, Packet, Payload, Rpc, Time, Timer},
+    runtime::{
+        LIVE_EVENTS, Runtime,
+        clock::ClockSkew,
+        codec::JsonLines,
+        container::{MockContainer, NodeSpec, STOPPED_MOCKS, UNLAUNCHABLE_MOCK_PREFIX},
+        end::EndCondition,
+        final_status,
+        history::{DecodeFailure, EventKind, Log, LogStream, Op, OpType},
+        input::{Status, Test},
+        kv::{KvService, LIN_KV},
+        routing::{Delivery, FaultKind, LinkFault, Middleware},
+        workload::{Workload, WorkloadKind},
+    },
 };
 
 #[tokio::test]
@@ -22,6 +41,7 @@ async fn test_runtime_launch_creates_and_saves_containers() {
             image_tag: "",
             env: vec![],
             end_delay_secs: 2,
+            ..Default::default()
         })
         .await;
 
@@ -44,6 +64,74 @@ async fn test_runtime_launch_creates_and_saves_containers() {
     }
 }
 
+#[tokio::test]
+async fn test_runtime_records_startup_time_per_node() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let nodenames = vec!["timed1", "timed2", "timed3", "timed4"];
+
+    let report = runtime
+        .launch_test(Test {
+            nodes: nodenames.iter().map(|s| s.to_string()).collect(),
+            end_delay_secs: 1,
+            launch_concurrency: Some(2),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(report.startup.nodes.len(), nodenames.len());
+    for name in nodenames {
+        assert!(report.startup.nodes[name] <= report.startup.total);
+    }
+}
+
+#[tokio::test]
+async fn test_runtime_stops_launched_nodes_when_one_fails_to_start() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let unlaunchable = format!("{}-node", UNLAUNCHABLE_MOCK_PREFIX);
+
+    let result = runtime
+        .launch_test(Test {
+            nodes: vec!["cleanup1".to_string(), unlaunchable, "cleanup2".to_string()],
+            end_delay_secs: 1,
+            ..Default::default()
+        })
+        .await;
+
+    assert!(result.is_err());
+    assert!(runtime.containers.is_empty());
+
+    let stopped = STOPPED_MOCKS.lock().unwrap();
+    assert!(stopped.contains(&"cleanup1".to_string()));
+    assert!(stopped.contains(&"cleanup2".to_string()));
+}
+
+#[test]
+fn test_container_names_are_scoped_to_run() {
+    let first = Runtime::<MockContainer>::new();
+    let second = Runtime::<MockContainer>::new();
+    assert_ne!(first.run_id(), second.run_id());
+
+    let spec = |run_id: &str, node_name: &str| NodeSpec {
+        image_name: "",
+        image_tag: "",
+        env: vec![],
+        node_name: node_name.to_string(),
+        run_id: run_id.to_string(),
+        codec: Arc::new(JsonLines(Default::default())),
+    };
+    let first_name = spec(first.run_id(), "node 1").container_name();
+    assert_eq!(first_name, format!("biv-{}-node_201", first.run_id()));
+    assert_ne!(first_name, spec(second.run_id(), "node 1").container_name());
+
+    // names that only differ in what docker doesn't take stay apart
+    let names: HashSet<String> = ["node 1", "node-1", "node_1", "node/1"]
+        .into_iter()
+        .map(|node| spec(first.run_id(), node).container_name())
+        .collect();
+    assert_eq!(names.len(), 4);
+}
+
 //test sending rpc packet
 #[tokio::test]
 async fn test_runtime_sends_and_receives_rpc_packets() {
@@ -63,7 +151,7 @@ async fn test_runtime_sends_and_receives_rpc_packets() {
         .expected_stdout_packets = Some(vec![Packet::Rpc(Rpc {
         src: "node1".to_string(),
         dst: "node2".to_string(),
-        data: String::new(),
+        data: String::new().into(),
     })]);
 
     let (stdin_tx, mut stdin_rx) = mpsc::channel(10);
@@ -71,7 +159,11 @@ async fn test_runtime_sends_and_receives_rpc_packets() {
 
     let (tx, rx) = oneshot::channel();
     // Act
-    let result = runtime.interconnect_nodes(tx, Duration::from_secs(2)).await;
+    let t = Test {
+        end_delay_secs: 2,
+        ..Default::default()
+    };
+    let result = runtime.interconnect_nodes(tx, &t, vec![], vec![]).await;
 
     assert!(result.is_ok());
     // Check if the rpc packet is received by node2
@@ -106,7 +198,7 @@ async fn test_runtime_sends_and_receives_broadcast_packets() {
         .unwrap()
         .expected_stdout_packets = Some(vec![Packet::Broadcast(Broadcast {
         src: "node1".to_string(),
-        data: String::new(),
+        data: String::new().into(),
     })]);
 
     let mut stdins = vec![];
@@ -119,7 +211,11 @@ async fn test_runtime_sends_and_receives_broadcast_packets() {
 
     let (tx, rx) = oneshot::channel();
     // Act
-    let result = runtime.interconnect_nodes(tx, Duration::from_secs(2)).await;
+    let t = Test {
+        end_delay_secs: 2,
+        ..Default::default()
+    };
+    let result = runtime.interconnect_nodes(tx, &t, vec![], vec![]).await;
 
     assert!(result.is_ok());
 
@@ -138,4 +234,648 @@ async fn test_runtime_sends_and_receives_broadcast_packets() {
     assert!(rx.await.unwrap().0.len() == 1);
 }
 
+//test multicast packet with a fault on one of its links
+#[tokio::test]
+async fn test_runtime_multicasts_to_subset_with_per_destination_faults() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let nodenames = vec!["node1", "node2", "node3", "node4"];
+
+    runtime.containers = nodenames
+        .iter()
+        .map(|name| (name.to_string(), MockContainer::new(name.to_string())))
+        .collect();
+
+    runtime
+        .containers
+        .get_mut("node1")
+        .unwrap()
+        .expected_stdout_packets = Some(vec![Packet::Multicast(Multicast {
+        src: "node1".to_string(),
+        dsts: vec!["node2".to_string(), "node3".to_string()],
+        data: String::new().into(),
+    })]);
+
+    let mut stdins = HashMap::new();
+    for node in &["node2", "node3", "node4"] {
+        let (stdin_tx, stdin_rx) = mpsc::channel(10);
+        runtime.containers.get_mut(*node).unwrap().expected_stdin = Some(stdin_tx);
+        stdins.insert(node.to_string(), stdin_rx);
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let t = Test {
+        end_delay_secs: 1,
+        faults: vec![LinkFault {
+            src: Some("node1".to_string()),
+            dst: Some("node3".to_string()),
+            kind: FaultKind::Drop,
+        }],
+        ..Default::default()
+    };
+    runtime
+        .interconnect_nodes(tx, &t, vec![], vec![])
+        .await
+        .unwrap();
+
+    assert!(rx.await.unwrap().0.len() == 1);
+
+    // only node2 gets it: node3's link drops it and node4 was never a destination
+    match stdins.get_mut("node2").unwrap().recv().await {
+        Some(Packet::Multicast(multicast)) => assert_eq!(multicast.src, "node1"),
+        other => panic!("expected multicast, got {:?}", other),
+    }
+    assert!(stdins.get_mut("node3").unwrap().try_recv().is_err());
+    assert!(stdins.get_mut("node4").unwrap().try_recv().is_err());
+}
+
+//custom middleware sees every delivery, here copying them to another node
+#[tokio::test(start_paused = true)]
+async fn test_runtime_routes_through_middleware() {
+    struct Mirror(&'static str);
+
+    impl Middleware for Mirror {
+        fn handle(&self, delivery: Delivery) -> Vec<Delivery> {
+            let copy = Delivery {
+                dst: self.0.to_string(),
+                delay: Duration::from_secs(1),
+                ..delivery.clone()
+            };
+            vec![delivery, copy]
+        }
+    }
+
+    let mut runtime = Runtime::<MockContainer>::new();
+    let nodenames = vec!["node1", "node2", "node3"];
+    runtime.containers = nodenames
+        .iter()
+        .map(|name| (name.to_string(), MockContainer::new(name.to_string())))
+        .collect();
+    runtime
+        .containers
+        .get_mut("node1")
+        .unwrap()
+        .expected_stdout_packets = Some(vec![Packet::Rpc(Rpc {
+        src: "node1".to_string(),
+        dst: "node2".to_string(),
+        data: String::new().into(),
+    })]);
+    let mut stdins = HashMap::new();
+    for node in &["node2", "node3"] {
+        let (stdin_tx, stdin_rx) = mpsc::channel(10);
+        runtime.containers.get_mut(*node).unwrap().expected_stdin = Some(stdin_tx);
+        stdins.insert(node.to_string(), stdin_rx);
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let t = Test {
+        end_delay_secs: 2,
+        middleware: vec![Arc::new(Mirror("node3"))],
+        ..Default::default()
+    };
+    let started_at = tokio::time::Instant::now();
+    runtime
+        .interconnect_nodes(tx, &t, vec![], vec![])
+        .await
+        .unwrap();
+
+    for node in ["node2", "node3"] {
+        match stdins.get_mut(node).unwrap().recv().await {
+            Some(Packet::Rpc(rpc)) => assert_eq!(rpc.dst, "node2"),
+            other => panic!("expected rpc, got {:?}", other),
+        }
+    }
+    // the copy was held back
+    assert_eq!(started_at.elapsed(), Duration::from_secs(1));
+    assert_eq!(rx.await.unwrap().0.len(), 1);
+}
+
+//nodes use the harness hosted lin-kv like any other node
+#[tokio::test]
+async fn test_runtime_answers_rpcs_to_kv_services() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let mut node = MockContainer::new("client".to_string());
+    let kv_rpc = |body: serde_json::Value| {
+        Packet::Rpc(Rpc {
+            src: "client".to_string(),
+            dst: LIN_KV.to_string(),
+            data: Payload::Json(body),
+        })
+    };
+    node.expected_stdout_packets = Some(vec![
+        kv_rpc(json!({"type": "write", "msg_id": 1, "key": "x", "value": 42})),
+        kv_rpc(json!({"type": "read", "msg_id": 2, "key": "x"})),
+    ]);
+    let (stdin_tx, mut stdin_rx) = mpsc::channel(10);
+    node.expected_stdin = Some(stdin_tx);
+    runtime.containers.insert("client".to_string(), node);
+
+    let (tx, rx) = oneshot::channel();
+    let t = Test {
+        end_delay_secs: 1,
+        services: vec![KvService::Linearizable],
+        ..Default::default()
+    };
+    runtime
+        .interconnect_nodes(tx, &t, vec![], vec![])
+        .await
+        .unwrap();
+
+    let mut replies = vec![];
+    for _ in 0..2 {
+        match stdin_rx.recv().await {
+            Some(Packet::Rpc(rpc)) => {
+                assert_eq!(rpc.src, LIN_KV);
+                replies.push(rpc.data.body().unwrap().into_owned());
+            }
+            other => panic!("expected rpc, got {:?}", other),
+        }
+    }
+    assert_eq!(replies[0], json!({"type": "write_ok", "in_reply_to": 1}));
+    assert_eq!(
+        replies[1],
+        json!({"type": "read_ok", "in_reply_to": 2, "value": 42})
+    );
+
+    // the service's replies are part of the history too
+    let history = rx.await.unwrap();
+    let from_kv = history.packets().filter(|(e, _)| e.node == LIN_KV);
+    assert_eq!(from_kv.count(), 2);
+}
+
+//workload clients can talk to harness hosted services as well as to nodes
+#[tokio::test(start_paused = true)]
+async fn test_runtime_records_workload_ops_at_configured_rate() {
+    let runtime = Runtime::<MockContainer>::new();
+    let (tx, rx) = oneshot::channel();
+    let t = Test {
+        end_delay_secs: 5,
+        services: vec![KvService::Linearizable],
+        workloads: vec![Workload {
+            kind: WorkloadKind::Register { keys: 2 },
+            nodes: vec![LIN_KV.to_string()],
+            concurrency: 2,
+            rate: Some(10.0),
+            limit: 20,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let started_at = tokio::time::Instant::now();
+    runtime
+        .interconnect_nodes(tx, &t, vec![], vec![])
+        .await
+        .unwrap();
+    let history = rx.await.unwrap();
+
+    let ops: Vec<&Op> = history.ops().map(|(_, op)| op).collect();
+    let invokes = ops.iter().filter(|op| op.op_type == OpType::Invoke);
+    assert_eq!(invokes.count(), 20);
+    // lin-kv answers everything, either for sure or with a definite error
+    let completions = ops.iter().filter(|op| op.op_type != OpType::Invoke);
+    assert!(completions.clone().all(|op| op.op_type != OpType::Info));
+    assert_eq!(completions.count(), 20);
+    assert!(ops.iter().any(|op| op.client == "c2"));
+
+    // 20 operations at 10 per second take about 2 seconds, plus the end delay
+    assert!(started_at.elapsed() >= Duration::from_secs(6));
+    assert!(started_at.elapsed() < Duration::from_secs(8));
+}
+
+//workload clients start talking to a node only once its init is queued
+#[tokio::test(start_paused = true)]
+async fn test_runtime_inits_nodes_before_workloads_start() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let mut node = MockContainer::new("n1".to_string());
+    let (stdin_tx, mut stdin_rx) = mpsc::channel(10);
+    node.expected_stdin = Some(stdin_tx);
+    runtime.containers.insert("n1".to_string(), node);
+
+    let (tx, _rx) = oneshot::channel();
+    let t = Test {
+        nodes: vec!["n1".to_string()],
+        input: HashMap::from([("n1".to_string(), vec![Payload::from("hello")])]),
+        end_delay_secs: 1,
+        workloads: vec![Workload {
+            kind: WorkloadKind::Counter,
+            limit: 1,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    runtime
+        .interconnect_nodes(tx, &t, vec![], vec![])
+        .await
+        .unwrap();
+
+    assert!(matches!(stdin_rx.recv().await, Some(Packet::Init(_))));
+    match stdin_rx.recv().await {
+        Some(Packet::Rpc(rpc)) => assert_eq!(rpc.src, "c1"),
+        other => panic!("expected the client's rpc, got {:?}", other),
+    }
+}
+
+//a workload that never goes quiet, ended by other conditions
+#[tokio::test(start_paused = true)]
+async fn test_runtime_ends_at_first_end_condition_met() {
+    let chatty = Test {
+        end_delay_secs: 1,
+        services: vec![KvService::Linearizable],
+        workloads: vec![Workload {
+            kind: WorkloadKind::Counter,
+            nodes: vec![LIN_KV.to_string()],
+            rate: Some(10.0),
+            limit: usize::MAX,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    let runtime = Runtime::<MockContainer>::new();
+    let (tx, rx) = oneshot::channel();
+    let end_conditions = vec![
+        EndCondition::Events(25),
+        EndCondition::After(Duration::from_secs(60)),
+    ];
+    runtime
+        .interconnect_nodes(tx, &chatty, end_conditions, vec![])
+        .await
+        .unwrap();
+    assert_eq!(rx.await.unwrap().0.len(), 25);
+
+    let (tx, rx) = oneshot::channel();
+    let end_conditions = vec![
+        EndCondition::After(Duration::from_secs(3)),
+        EndCondition::quiescent(Duration::from_secs(1)),
+    ];
+    let started_at = tokio::time::Instant::now();
+    runtime
+        .interconnect_nodes(tx, &chatty, end_conditions, vec![])
+        .await
+        .unwrap();
+    let history = rx.await.unwrap();
+    assert_eq!(started_at.elapsed(), Duration::from_secs(3));
+    assert!(history.ops().count() > 20);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_runtime_times_out_nodes_that_never_go_quiet() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let t = Test {
+        nodes: vec!["busy1".to_string()],
+        end_delay_secs: 1,
+        max_duration: Some(Duration::from_secs(2)),
+        services: vec![KvService::Linearizable],
+        workloads: vec![Workload {
+            kind: WorkloadKind::Counter,
+            nodes: vec![LIN_KV.to_string()],
+            rate: Some(10.0),
+            limit: usize::MAX,
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let started_at = tokio::time::Instant::now();
+    let report = runtime.launch_test(t).await.unwrap();
+    let history = report.history;
+
+    assert_eq!(started_at.elapsed(), Duration::from_secs(2));
+    // what happened until then is kept
+    assert!(history.ops().count() > 20);
+    assert_eq!(history.0.last().unwrap().at, Duration::from_secs(2));
+    assert_eq!(
+        report.status,
+        Status::TimedOut {
+            after: Duration::from_secs(2)
+        }
+    );
+    // the nodes were still busy, so they are stopped
+    assert!(runtime.containers.is_empty());
+    assert!(STOPPED_MOCKS.lock().unwrap().contains(&"busy1".to_string()));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_runtime_streams_events_while_the_test_runs() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let mut run = runtime
+        .start_test(Test {
+            end_delay_secs: 1,
+            services: vec![KvService::Linearizable],
+            workloads: vec![Workload {
+                kind: WorkloadKind::Register { keys: 1 },
+                nodes: vec![LIN_KV.to_string()],
+                rate: Some(10.0),
+                limit: 10,
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // the first event comes long before the run ends
+    let started_at = tokio::time::Instant::now();
+    let first = run.next().await.unwrap();
+    assert!(started_at.elapsed() < Duration::from_secs(1));
+
+    let mut streamed = vec![first];
+    while let Some(event) = run.next().await {
+        streamed.push(event);
+    }
+    let report = run.finish().await.unwrap();
+    assert_eq!(report.status, Status::Completed);
+    assert_eq!(streamed, report.history.0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_runtime_leaves_events_out_of_a_full_stream() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let mut run = runtime
+        .start_test(Test {
+            end_delay_secs: 1,
+            services: vec![KvService::Linearizable],
+            workloads: vec![Workload {
+                kind: WorkloadKind::Register { keys: 1 },
+                nodes: vec![LIN_KV.to_string()],
+                limit: LIVE_EVENTS,
+                ..Default::default()
+            }],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    // nobody reads the stream until the run is over
+    tokio::time::sleep(Duration::from_secs(60)).await;
+    let streamed: Vec<_> = run.by_ref().collect().await;
+    let report = run.finish().await.unwrap();
+    assert_eq!(streamed.len(), LIVE_EVENTS);
+    assert_eq!(streamed, report.history.0[..LIVE_EVENTS]);
+    assert!(report.history.0.len() > LIVE_EVENTS);
+}
+
+//timers fire in virtual time when tokio's clock is paused
+#[tokio::test(start_paused = true)]
+async fn test_runtime_delivers_ticks_for_timers() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let mut node = MockContainer::new("sleeper".to_string());
+    node.expected_stdout_packets = Some(vec![Packet::Timer(Timer {
+        src: "sleeper".to_string(),
+        after_ms: 60_000,
+        data: "election".into(),
+    })]);
+    let (stdin_tx, mut stdin_rx) = mpsc::channel(10);
+    node.expected_stdin = Some(stdin_tx);
+    runtime.containers.insert("sleeper".to_string(), node);
+
+    let started_at = tokio::time::Instant::now();
+    let (tx, _rx) = oneshot::channel();
+    let t = Test {
+        end_delay_secs: 120,
+        ..Default::default()
+    };
+    runtime
+        .interconnect_nodes(tx, &t, vec![], vec![])
+        .await
+        .unwrap();
+
+    match stdin_rx.recv().await {
+        Some(Packet::Tick(tick)) => {
+            assert_eq!(tick.node_id, "sleeper");
+            assert_eq!(tick.data, "election".into());
+        }
+        other => panic!("expected tick, got {:?}", other),
+    }
+    assert!(started_at.elapsed() >= Duration::from_secs(60));
+}
+
+//a skewed node's timers and time requests go by its own clock
+#[tokio::test(start_paused = true)]
+async fn test_runtime_delivers_ticks_and_time_by_skewed_clock() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let mut node = MockContainer::new("sleeper".to_string());
+    node.expected_stdout_packets = Some(vec![
+        Packet::Time(Time {
+            node_id: "sleeper".to_string(),
+            now_ms: None,
+        }),
+        Packet::Timer(Timer {
+            src: "sleeper".to_string(),
+            after_ms: 60_000,
+            data: "election".into(),
+        }),
+    ]);
+    let (stdin_tx, mut stdin_rx) = mpsc::channel(10);
+    node.expected_stdin = Some(stdin_tx);
+    runtime.containers.insert("sleeper".to_string(), node);
+
+    let started_at = tokio::time::Instant::now();
+    let (tx, _rx) = oneshot::channel();
+    let skew = ClockSkew {
+        offset_ms: 10_000,
+        drift: 1.0,
+    };
+    let t = Test {
+        end_delay_secs: 120,
+        clock_skew: HashMap::from([("sleeper".to_string(), skew)]),
+        ..Default::default()
+    };
+    runtime
+        .interconnect_nodes(tx, &t, vec![], vec![])
+        .await
+        .unwrap();
+
+    let time_ms = match stdin_rx.recv().await {
+        Some(Packet::Time(Time {
+            now_ms: Some(now_ms),
+            ..
+        })) => now_ms,
+        other => panic!("expected time, got {:?}", other),
+    };
+    let real_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    assert!(time_ms >= real_ms + 9_000);
+
+    // the node's clock runs twice as fast, so a minute on it is half a minute for real
+    match stdin_rx.recv().await {
+        Some(Packet::Tick(tick)) => {
+            assert_eq!(tick.node_id, "sleeper");
+            assert_eq!(tick.data, "election".into());
+            assert!(tick.now_ms >= time_ms + 60_000);
+        }
+        other => panic!("expected tick, got {:?}", other),
+    }
+    assert!(started_at.elapsed() >= Duration::from_secs(30));
+    assert!(started_at.elapsed() < Duration::from_secs(60));
+}
+
 //multi node test...
+
+#[tokio::test]
+async fn test_runtime_records_logs_in_history_and_log_files() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let mut node = MockContainer::new("logger".to_string());
+    node.expected_logs = Some(vec![
+        Log {
+            stream: LogStream::Stdout,
+            line: "booting".to_string(),
+        },
+        Log {
+            stream: LogStream::Stderr,
+            line: "PANIC: oops".to_string(),
+        },
+    ]);
+    runtime.containers.insert("logger".to_string(), node);
+
+    let log_dir = std::env::temp_dir().join(format!("biv-test-{}", runtime.run_id()));
+    let (tx, rx) = oneshot::channel();
+    let t = Test {
+        end_delay_secs: 1,
+        log_dir: Some(log_dir.clone()),
+        ..Default::default()
+    };
+    runtime
+        .interconnect_nodes(tx, &t, vec![], vec![])
+        .await
+        .unwrap();
+
+    let history = rx.await.unwrap();
+    let logs: Vec<&Log> = history.logs_of("logger").collect();
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[1].stream, LogStream::Stderr);
+    assert!(!NoLogContaining::new("PANIC").check(&history).valid);
+
+    let written = std::fs::read_to_string(log_dir.join("logger.log")).unwrap();
+    assert!(written.contains("stdout: booting"));
+    assert!(written.contains("stderr: PANIC: oops"));
+    std::fs::remove_dir_all(log_dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_runtime_stops_at_first_decode_failure_in_strict_mode() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let mut node = MockContainer::new("garbler".to_string());
+    node.expected_decode_failures = Some(vec![
+        DecodeFailure {
+            raw: b"{\"type\": 42}".to_vec(),
+            error: "invalid type".to_string(),
+        },
+        DecodeFailure {
+            raw: b"{oops}".to_vec(),
+            error: "key must be a string".to_string(),
+        },
+    ]);
+    runtime.containers.insert("garbler".to_string(), node);
+
+    let (tx, rx) = oneshot::channel();
+    let t = Test {
+        end_delay_secs: 1,
+        strict_decoding: true,
+        ..Default::default()
+    };
+    runtime
+        .interconnect_nodes(tx, &t, vec![], vec![])
+        .await
+        .unwrap();
+
+    let history = rx.await.unwrap();
+    assert_eq!(history.decode_failures().count(), 1);
+    assert_eq!(
+        final_status(&t, &history),
+        Status::DecodeFailed {
+            node: "garbler".to_string(),
+            error: "invalid type".to_string(),
+        }
+    );
+
+    let lenient = Test::default();
+    assert_eq!(final_status(&lenient, &history), Status::Completed);
+}
+
+#[tokio::test]
+async fn test_runtime_aborts_at_first_violated_invariant() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let mut node = MockContainer::new("crasher".to_string());
+    let log = |line: &str| Log {
+        stream: LogStream::Stderr,
+        line: line.to_string(),
+    };
+    node.expected_logs = Some(vec![
+        log("starting"),
+        log("elected"),
+        log("PANIC: term went backwards"),
+        log("still running"),
+    ]);
+    runtime.containers.insert("crasher".to_string(), node);
+
+    let (tx, rx) = oneshot::channel();
+    let t = Test {
+        end_delay_secs: 1,
+        snapshot_events: Some(2),
+        ..Default::default()
+    };
+    let invariants = vec![
+        invariant("never silent", |e| match &e.kind {
+            EventKind::Log(log) if log.line.is_empty() => Err("empty line".to_string()),
+            _ => Ok(()),
+        }),
+        Box::new(NoLogContaining::new("PANIC")),
+    ];
+    runtime
+        .interconnect_nodes(tx, &t, vec![], invariants)
+        .await
+        .unwrap();
+
+    let history = rx.await.unwrap();
+    // nothing after the offending line makes it into the history
+    assert_eq!(history.logs().count(), 3);
+    match final_status(&t, &history) {
+        Status::InvariantViolated {
+            invariant,
+            node,
+            snapshot,
+            ..
+        } => {
+            assert_eq!(invariant, "no log containing \"PANIC\"");
+            assert_eq!(node, "crasher");
+            assert_eq!(snapshot.len(), 2);
+            assert!(snapshot[1].to_string().contains("PANIC"));
+        }
+        other => panic!("expected a violated invariant, got {:?}", other),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_runtime_stops_everything_of_an_aborted_run() {
+    let mut runtime = Runtime::<MockContainer>::new();
+    let report = runtime
+        .launch_test(Test {
+            nodes: vec!["aborted1".to_string()],
+            end_delay_secs: 1,
+            services: vec![KvService::Linearizable],
+            workloads: vec![Workload {
+                kind: WorkloadKind::Counter,
+                nodes: vec![LIN_KV.to_string()],
+                rate: Some(10.0),
+                limit: usize::MAX,
+                ..Default::default()
+            }],
+            invariants: vec![invariant("no ops", |e| match &e.kind {
+                EventKind::Op(_) => Err("an op".to_string()),
+                _ => Ok(()),
+            })],
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    assert!(report.status.is_aborted());
+    assert!(runtime.containers.is_empty());
+    assert!(
+        STOPPED_MOCKS
+            .lock()
+            .unwrap()
+            .contains(&"aborted1".to_string())
+    );
+}
@@ -0,0 +1,436 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use serde_json::{Value, json};
+use tokio::{
+    sync::mpsc,
+    time::{self, timeout},
+};
+
+use crate::{
+    packet::{NodeId, Packet, Payload, Rpc},
+    runtime::history::{EventKind, Op, OpType},
+    util::Rng,
+};
+
+// Client operations generated against the nodes under test. Every client is a pseudo
+// node that sends one Rpc at a time to its node and waits for the reply, recording the
+// invocation and completion of each operation in the history.
+#[derive(Clone, Debug)]
+pub struct Workload {
+    pub kind: WorkloadKind,
+    // nodes the clients talk to, spread round robin. Empty means every node of the test
+    pub nodes: Vec<NodeId>,
+    // how many clients have an operation in flight at the same time
+    pub concurrency: usize,
+    // operations per second over all clients. None issues them as fast as nodes reply
+    pub rate: Option<f64>,
+    // total number of operations to issue
+    pub limit: usize,
+    // how long a client waits for a reply before giving up with an Info completion
+    pub timeout: Duration,
+    pub seed: u64,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Workload {
+            kind: WorkloadKind::Register { keys: 1 },
+            nodes: vec![],
+            concurrency: 1,
+            rate: None,
+            limit: 100,
+            timeout: Duration::from_secs(1),
+            seed: 0,
+        }
+    }
+}
+
+// The operations a workload generates, with maelstrom style request bodies.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WorkloadKind {
+    // read, write and cas with "key", "value", "from" and "to"
+    Register {
+        keys: usize,
+    },
+    // add with a positive "delta", and read of the total
+    Counter,
+    // add with a unique "element", and read of the whole set
+    Set,
+    // txn with a "txn" of ["append", key, unique value] and ["r", key, null] micro-ops
+    Append {
+        keys: usize,
+        max_txn_len: usize,
+    },
+    // transfer of an "amount" "from" one account "to" another, with every read_every-th
+    // operation of a client a read of all balances
+    Bank {
+        accounts: usize,
+        max_amount: u64,
+        read_every: usize,
+    },
+    // broadcast of a unique "message", and read of every message the node has seen
+    Broadcast,
+    // kafka style: send of a unique "msg" to the log of a "key", poll of the messages
+    // after the client's "offsets", commit_offsets of what it has polled, and
+    // list_committed_offsets of all "keys"
+    Log {
+        keys: usize,
+    },
+}
+
+// What a client remembers between its operations.
+#[derive(Default)]
+struct ClientState {
+    // operations issued so far
+    n: u64,
+    // next offset to poll per log key
+    positions: BTreeMap<String, u64>,
+}
+
+impl ClientState {
+    fn observe(&mut self, completed: &Op) {
+        if completed.f != "poll" || completed.op_type != OpType::Ok {
+            return;
+        }
+        let Some(msgs) = completed.value.get("msgs").and_then(Value::as_object) else {
+            return;
+        };
+        for (key, msgs) in msgs {
+            let last = msgs
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|msg| msg.get(0)?.as_u64())
+                .max();
+            if let Some(last) = last {
+                let position = self.positions.entry(key.clone()).or_default();
+                *position = (*position).max(last + 1);
+            }
+        }
+    }
+}
+
+impl WorkloadKind {
+    // The f and body (without type and msg_id) of a client's next operation.
+    fn generate(
+        &self,
+        client: &ClientState,
+        rng: &mut Rng,
+        unique: &AtomicU64,
+    ) -> (&'static str, Value) {
+        let mut pick = |n: usize| rng.below(n.max(1) as u64);
+        match *self {
+            WorkloadKind::Register { keys } => {
+                let key = pick(keys);
+                match pick(3) {
+                    0 => ("read", json!({"key": key})),
+                    1 => ("write", json!({"key": key, "value": pick(5)})),
+                    _ => ("cas", json!({"key": key, "from": pick(5), "to": pick(5)})),
+                }
+            }
+            WorkloadKind::Counter => match pick(2) {
+                0 => ("add", json!({"delta": pick(5) + 1})),
+                _ => ("read", json!({})),
+            },
+            WorkloadKind::Set => match pick(2) {
+                0 => {
+                    let element = unique.fetch_add(1, Ordering::Relaxed);
+                    ("add", json!({ "element": element }))
+                }
+                _ => ("read", json!({})),
+            },
+            WorkloadKind::Append { keys, max_txn_len } => {
+                let txn: Vec<Value> = (0..=pick(max_txn_len))
+                    .map(|_| {
+                        let key = pick(keys);
+                        match pick(2) {
+                            0 => json!(["append", key, unique.fetch_add(1, Ordering::Relaxed)]),
+                            _ => json!(["r", key, null]),
+                        }
+                    })
+                    .collect();
+                ("txn", json!({ "txn": txn }))
+            }
+            WorkloadKind::Bank {
+                accounts,
+                max_amount,
+                read_every,
+            } => {
+                if client.n.is_multiple_of(read_every.max(1) as u64) {
+                    return ("read", json!({}));
+                }
+                let accounts = accounts.max(2);
+                let from = pick(accounts);
+                // any other account
+                let to = (from + 1 + pick(accounts - 1)) % accounts as u64;
+                let amount = pick(max_amount as usize) + 1;
+                (
+                    "transfer",
+                    json!({"from": from, "to": to, "amount": amount}),
+                )
+            }
+            WorkloadKind::Broadcast => match pick(2) {
+                0 => {
+                    let message = unique.fetch_add(1, Ordering::Relaxed);
+                    ("broadcast", json!({ "message": message }))
+                }
+                _ => ("read", json!({})),
+            },
+            WorkloadKind::Log { keys } => {
+                let all_keys = (0..keys.max(1)).map(|k| k.to_string());
+                match pick(4) {
+                    0 | 1 => {
+                        let msg = unique.fetch_add(1, Ordering::Relaxed);
+                        ("send", json!({"key": pick(keys).to_string(), "msg": msg}))
+                    }
+                    2 => {
+                        let offsets: BTreeMap<String, u64> = all_keys
+                            .map(|k| (k.clone(), client.positions.get(&k).copied().unwrap_or(0)))
+                            .collect();
+                        ("poll", json!({ "offsets": offsets }))
+                    }
+                    _ if pick(2) == 0 => {
+                        // processed up to and including the last polled message
+                        let offsets: BTreeMap<&String, u64> =
+                            client.positions.iter().map(|(k, p)| (k, p - 1)).collect();
+                        ("commit_offsets", json!({ "offsets": offsets }))
+                    }
+                    _ => {
+                        let keys: Vec<String> = all_keys.collect();
+                        ("list_committed_offsets", json!({ "keys": keys }))
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Maelstrom error codes that say for sure the operation did not happen.
+const DEFINITE_ERRORS: [u64; 9] = [1, 10, 11, 12, 14, 20, 21, 22, 30];
+
+// A client of a started workload, wired up by the runtime like any other node.
+pub(crate) struct Client {
+    pub id: NodeId,
+    pub input_tx: mpsc::Sender<Packet>,
+    pub output_rx: mpsc::Receiver<EventKind>,
+}
+
+struct Shared {
+    kind: WorkloadKind,
+    issued: AtomicUsize,
+    unique: AtomicU64,
+    limit: usize,
+    timeout: Duration,
+    // time between two operations of the same client
+    period: Option<Duration>,
+}
+
+impl Workload {
+    // Starts the clients, named c<first_client>, c<first_client + 1>, ...
+    pub(crate) fn start(&self, first_client: usize, nodes: &[NodeId]) -> Vec<Client> {
+        let targets = if self.nodes.is_empty() {
+            nodes
+        } else {
+            &self.nodes
+        };
+        if targets.is_empty() {
+            return vec![];
+        }
+
+        let concurrency = self.concurrency.max(1);
+        let shared = Arc::new(Shared {
+            kind: self.kind.clone(),
+            issued: AtomicUsize::new(0),
+            unique: AtomicU64::new(0),
+            limit: self.limit,
+            timeout: self.timeout,
+            period: self
+                .rate
+                .map(|rate| Duration::from_secs_f64(concurrency as f64 / rate)),
+        });
+
+        (0..concurrency)
+            .map(|i| {
+                let id = format!("c{}", first_client + i);
+                let node = targets[i % targets.len()].clone();
+                let (input_tx, input_rx) = mpsc::channel(50);
+                let (output_tx, output_rx) = mpsc::channel(50);
+                let rng = Rng::new(self.seed.wrapping_add(i as u64 + 1));
+                tokio::spawn(run_client(
+                    id.clone(),
+                    node,
+                    shared.clone(),
+                    rng,
+                    input_rx,
+                    output_tx,
+                ));
+                Client {
+                    id,
+                    input_tx,
+                    output_rx,
+                }
+            })
+            .collect()
+    }
+}
+
+async fn run_client(
+    id: NodeId,
+    node: NodeId,
+    shared: Arc<Shared>,
+    mut rng: Rng,
+    mut input_rx: mpsc::Receiver<Packet>,
+    output_tx: mpsc::Sender<EventKind>,
+) {
+    let mut next_at = time::Instant::now();
+    let mut state = ClientState::default();
+    while shared.issued.fetch_add(1, Ordering::Relaxed) < shared.limit {
+        if let Some(period) = shared.period {
+            time::sleep_until(next_at).await;
+            next_at += period;
+        }
+        state.n += 1;
+        let msg_id = state.n;
+
+        let (f, value) = shared.kind.generate(&state, &mut rng, &shared.unique);
+        let op = Op {
+            client: id.clone(),
+            node: node.clone(),
+            msg_id,
+            op_type: OpType::Invoke,
+            f: f.to_string(),
+            value,
+        };
+        let mut body = op.value.clone();
+        body["type"] = json!(f);
+        body["msg_id"] = json!(msg_id);
+        let request = Packet::Rpc(Rpc {
+            src: id.clone(),
+            dst: node.clone(),
+            data: Payload::Json(body),
+        });
+        if output_tx.send(EventKind::Op(op.clone())).await.is_err()
+            || output_tx.send(EventKind::Packet(request)).await.is_err()
+        {
+            return;
+        }
+
+        let (op_type, value) = match timeout(shared.timeout, reply_to(&mut input_rx, msg_id)).await
+        {
+            Ok(Some(reply)) => completion(op.value.clone(), &reply),
+            Ok(None) => return,
+            Err(_) => (OpType::Info, op.value.clone()),
+        };
+        let completed = Op {
+            op_type,
+            value,
+            ..op
+        };
+        state.observe(&completed);
+        if output_tx.send(EventKind::Op(completed)).await.is_err() {
+            return;
+        }
+    }
+}
+
+// Waits for the reply to msg_id, skipping late replies to operations that timed out.
+async fn reply_to(input_rx: &mut mpsc::Receiver<Packet>, msg_id: u64) -> Option<Value> {
+    while let Some(packet) = input_rx.recv().await {
+        if let Some(body) = packet.body()
+            && body.get("in_reply_to").and_then(Value::as_u64) == Some(msg_id)
+        {
+            return Some(body.into_owned());
+        }
+    }
+    None
+}
+
+fn completion(mut value: Value, reply: &Value) -> (OpType, Value) {
+    let op_type = match reply["type"].as_str() {
+        Some("error") => match reply["code"].as_u64() {
+            Some(code) if DEFINITE_ERRORS.contains(&code) => OpType::Fail,
+            _ => OpType::Info,
+        },
+        _ => OpType::Ok,
+    };
+    if let (Value::Object(value), Value::Object(reply)) = (&mut value, reply) {
+        for (k, v) in reply {
+            if k != "type" && k != "in_reply_to" {
+                value.insert(k.clone(), v.clone());
+            }
+        }
+    }
+    (op_type, value)
+}
+
+#[test]
+fn test_completion_merges_reply_and_classifies_errors() {
+    let read = json!({"key": 1});
+    let (op_type, value) = completion(read.clone(), &json!({"type": "read_ok", "value": 3}));
+    assert_eq!(op_type, OpType::Ok);
+    assert_eq!(value, json!({"key": 1, "value": 3}));
+
+    let missing = json!({"type": "error", "code": 20, "text": "key does not exist"});
+    assert_eq!(completion(read.clone(), &missing).0, OpType::Fail);
+    let crashed = json!({"type": "error", "code": 13});
+    assert_eq!(completion(read, &crashed).0, OpType::Info);
+}
+
+#[test]
+fn test_bank_transfers_between_different_accounts_and_reads_periodically() {
+    let kind = WorkloadKind::Bank {
+        accounts: 3,
+        max_amount: 5,
+        read_every: 4,
+    };
+    let mut rng = Rng::new(7);
+    for n in 1..=100 {
+        let client = ClientState {
+            n,
+            ..Default::default()
+        };
+        match kind.generate(&client, &mut rng, &AtomicU64::new(0)) {
+            ("transfer", body) => {
+                assert_ne!(n % 4, 0);
+                assert_ne!(body["from"], body["to"]);
+                assert!((1..=5).contains(&body["amount"].as_u64().unwrap()));
+            }
+            (f, _) => assert_eq!((f, n % 4), ("read", 0)),
+        }
+    }
+}
+
+#[test]
+fn test_log_clients_poll_after_what_they_have_seen() {
+    let mut client = ClientState::default();
+    client.observe(&Op {
+        client: "c1".to_string(),
+        node: "n1".to_string(),
+        msg_id: 1,
+        op_type: OpType::Ok,
+        f: "poll".to_string(),
+        value: json!({"offsets": {"0": 0}, "msgs": {"0": [[0, 5], [3, 7]]}}),
+    });
+    assert_eq!(client.positions["0"], 4);
+
+    let kind = WorkloadKind::Log { keys: 2 };
+    let mut rng = Rng::new(3);
+    let polls: Vec<Value> = (0..40)
+        .map(|_| kind.generate(&client, &mut rng, &AtomicU64::new(0)))
+        .filter(|(f, _)| *f == "poll")
+        .map(|(_, body)| body)
+        .collect();
+    assert!(!polls.is_empty());
+    assert!(
+        polls
+            .iter()
+            .all(|p| p["offsets"] == json!({"0": 4, "1": 0}))
+    );
+}
@@ -1,4 +1,8 @@
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 pub trait ErrorLoggable {
     fn log_on_error(&self);
@@ -14,3 +18,42 @@ impl<T, E: Debug> ErrorLoggable for Result<T, E> {
         }
     }
 }
+
+// Short id that is unique across processes and across runtimes within a process. The
+// fields are separated, without that e.g. pid 1 with counter 23 and pid 12 with counter 3
+// would make the same id.
+pub fn new_run_id() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    format!(
+        "{:x}-{:x}-{:x}",
+        millis,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+// Small deterministic generator (xorshift64*), so runs with the same seed make the same choices.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // Uniform-ish in 0..n, n must not be zero.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}